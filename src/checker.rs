@@ -0,0 +1,121 @@
+use crate::domain::{Expr, KeywordType, Token, TokenType};
+use std::fmt::{Display, Formatter};
+
+/// статический тип выражения, Any - значение неизвестно до выполнения (идентификатор)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Type {
+    Number,
+    String,
+    Bool,
+    Nil,
+    Any,
+}
+
+impl Display for Type {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Type::Number => "number",
+            Type::String => "string",
+            Type::Bool => "bool",
+            Type::Nil => "nil",
+            Type::Any => "any",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// операция, которая гарантированно упадет в рантайме, token - ее оператор
+#[derive(Debug, Clone, PartialEq)]
+pub struct TypeError {
+    pub message: String,
+    pub token: Token,
+}
+
+impl Display for TypeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "[line {}:{}] Error at '{}': {}",
+            self.token._line,
+            self.token._column_from,
+            self.token.lexeme(),
+            self.message
+        )
+    }
+}
+
+/// постепенная проверка: Any совместим с любым типом, поэтому код без известных типов ошибок не дает
+pub fn check(expr: &Expr) -> (Type, Vec<TypeError>) {
+    let mut errors = Vec::new();
+    let _type = infer(expr, &mut errors);
+    (_type, errors)
+}
+
+fn infer(expr: &Expr, errors: &mut Vec<TypeError>) -> Type {
+    match expr {
+        Expr::Literal(_, token) => literal(token),
+        Expr::Grouping(inner) => infer(inner, errors),
+        Expr::Unary(operator, right) => {
+            let right = infer(right, errors);
+            match operator._type {
+                TokenType::MINUS => {
+                    if !number(right) {
+                        error(errors, operator, format!("Operand must be a number, found {}.", right));
+                    }
+                    Type::Number
+                }
+                _ => Type::Bool,
+            }
+        }
+        Expr::Binary(left, operator, right) => {
+            let left = infer(left, errors);
+            let right = infer(right, errors);
+            match operator._type {
+                TokenType::EQUAL_EQUAL | TokenType::BANG_EQUAL => Type::Bool,
+                TokenType::PLUS => match (left, right) {
+                    (Type::Any, Type::Number | Type::String | Type::Any) => right,
+                    (Type::Number | Type::String, Type::Any) => left,
+                    _ if left == right && matches!(left, Type::Number | Type::String) => left,
+                    _ => {
+                        let message = format!("Operands must be two numbers or two strings, found {} and {}.", left, right);
+                        error(errors, operator, message);
+                        // тип результата неизвестен, дальше ошибка не размножается
+                        Type::Any
+                    }
+                },
+                TokenType::GREATER | TokenType::GREATER_EQUAL | TokenType::LESS | TokenType::LESS_EQUAL => {
+                    numbers(errors, operator, left, right);
+                    Type::Bool
+                }
+                _ => {
+                    numbers(errors, operator, left, right);
+                    Type::Number
+                }
+            }
+        }
+    }
+}
+
+fn literal(token: &Token) -> Type {
+    match token._type {
+        TokenType::NUMBER => Type::Number,
+        TokenType::STRING => Type::String,
+        TokenType::KEYWORD(KeywordType::TRUE | KeywordType::FALSE) => Type::Bool,
+        TokenType::KEYWORD(KeywordType::NIL) => Type::Nil,
+        _ => Type::Any,
+    }
+}
+
+fn number(_type: Type) -> bool {
+    matches!(_type, Type::Number | Type::Any)
+}
+
+fn numbers(errors: &mut Vec<TypeError>, operator: &Token, left: Type, right: Type) {
+    if !number(left) || !number(right) {
+        error(errors, operator, format!("Operands must be numbers, found {} and {}.", left, right));
+    }
+}
+
+fn error(errors: &mut Vec<TypeError>, operator: &Token, message: String) {
+    errors.push(TypeError { message, token: operator.clone() });
+}
@@ -0,0 +1,276 @@
+use crate::domain::{ParserError, Token, TokenType, Tokens};
+use crate::parser::MAX_NESTING;
+use std::cell::Cell;
+
+/// конкретное дерево разбора: в отличие от AST хранит все токены (вместе с trivia),
+/// поэтому `text()` корня возвращает исходник байт в байт, даже если в нем есть ошибки
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyntaxNode {
+    pub kind: SyntaxKind,
+    pub children: Vec<SyntaxElement>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SyntaxElement {
+    Node(SyntaxNode),
+    Token(Token),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SyntaxKind {
+    /// весь файл, последний ребенок - EOF с хвостовыми trivia
+    Root,
+    Literal,
+    Unary,
+    Binary,
+    Grouping,
+    /// `( ... )`, внутри которых не одно выражение: `f()`, `f(a, b)`, `(var i = 0; i < 3; i = i + 1)`,
+    /// дети - скобки и между ними выражения и одиночные токены
+    Parens,
+    /// токен, с которого не начинается выражение
+    Error,
+    /// токен, на котором вложенность дошла до MAX_NESTING, глубже дерево не строится
+    TooDeep,
+}
+
+impl SyntaxNode {
+    fn new(kind: SyntaxKind, children: Vec<SyntaxElement>) -> Self {
+        SyntaxNode { kind, children }
+    }
+
+    /// исходный текст узла вместе с trivia
+    #[cfg(test)]
+    pub fn text(&self) -> String {
+        let mut text = String::new();
+        for token in self.tokens() {
+            for trivia in &token._trivia {
+                text.push_str(trivia.text());
+            }
+            text.push_str(token.lexeme());
+        }
+        text
+    }
+
+    /// токены узла в порядке исходника
+    pub fn tokens(&self) -> Vec<&Token> {
+        let mut tokens = Vec::new();
+        for child in &self.children {
+            match child {
+                SyntaxElement::Node(node) => tokens.extend(node.tokens()),
+                SyntaxElement::Token(token) => tokens.push(token),
+            }
+        }
+        tokens
+    }
+}
+
+/// строит CST, грамматика та же, что у parser, но бинарные операторы левоассоциативны
+/// и разбор никогда не падает: лишние токены уходят в Error узлы
+/// для round-trip токены должны быть получены через `lexer::tokenize_lossless`
+pub fn build(tokens: Tokens) -> SyntaxNode {
+    let builder = Builder::new(&tokens.tokens, 0);
+
+    let mut children = Vec::new();
+    while !builder.at_end() {
+        children.push(SyntaxElement::Node(top_level(&builder)));
+    }
+    children.push(builder.advance());
+
+    SyntaxNode::new(SyntaxKind::Root, children)
+}
+
+/// один ребенок Root, начиная с токена start, и индекс токена после него
+/// узел зависит только от своих токенов и одного токена после них
+pub fn build_top_level(tokens: &[Token], start: usize) -> (SyntaxNode, usize) {
+    let builder = Builder::new(tokens, start);
+    let node = top_level(&builder);
+    (node, builder.current.get())
+}
+
+fn top_level(builder: &Builder) -> SyntaxNode {
+    match expression(builder) {
+        Some(node) => node,
+        None => SyntaxNode::new(SyntaxKind::Error, vec![builder.advance()]),
+    }
+}
+
+fn expression(builder: &Builder) -> Option<SyntaxNode> {
+    builder.nested(equality)
+}
+
+/// equality -> != ==
+fn equality(builder: &Builder) -> Option<SyntaxNode> {
+    binary(builder, &[TokenType::BANG_EQUAL, TokenType::EQUAL_EQUAL], comparison)
+}
+
+/// comparison -> > >= < <=
+fn comparison(builder: &Builder) -> Option<SyntaxNode> {
+    binary(builder, &[TokenType::GREATER, TokenType::GREATER_EQUAL, TokenType::LESS, TokenType::LESS_EQUAL], term)
+}
+
+/// term -> + -
+fn term(builder: &Builder) -> Option<SyntaxNode> {
+    binary(builder, &[TokenType::MINUS, TokenType::PLUS], factor)
+}
+
+/// factor -> / *
+fn factor(builder: &Builder) -> Option<SyntaxNode> {
+    binary(builder, &[TokenType::SLASH, TokenType::STAR], unary)
+}
+
+/// левоассоциативная цепочка `operand (operator operand)*`, правый операнд может отсутствовать
+fn binary(builder: &Builder,
+          operators: &[TokenType],
+          operand: fn(&Builder) -> Option<SyntaxNode>) -> Option<SyntaxNode> {
+    let mut node = operand(builder)?;
+
+    while builder.check(operators) {
+        let mut children = vec![SyntaxElement::Node(node), builder.advance()];
+        if let Some(right) = operand(builder) {
+            children.push(SyntaxElement::Node(right));
+        }
+        node = SyntaxNode::new(SyntaxKind::Binary, children);
+    }
+
+    Some(node)
+}
+
+/// unary -> ! -
+fn unary(builder: &Builder) -> Option<SyntaxNode> {
+    if builder.check(&[TokenType::BANG, TokenType::MINUS]) {
+        let mut children = vec![builder.advance()];
+        if let Some(right) = builder.nested(unary) {
+            children.push(SyntaxElement::Node(right));
+        }
+        Some(SyntaxNode::new(SyntaxKind::Unary, children))
+    } else {
+        primary(builder)
+    }
+}
+
+/// literal -> string, number, identifier, keyword, (, )
+fn primary(builder: &Builder) -> Option<SyntaxNode> {
+    let token = builder.peek();
+    match token._type {
+        TokenType::NUMBER | TokenType::STRING | TokenType::IDENTIFIER | TokenType::KEYWORD(_) => {
+            Some(SyntaxNode::new(SyntaxKind::Literal, vec![builder.advance()]))
+        }
+        TokenType::LEFT_PAREN => {
+            let mut children = vec![builder.advance()];
+            if let Some(inner) = expression(builder) {
+                children.push(SyntaxElement::Node(inner));
+                if builder.check(&[TokenType::RIGHT_PAREN]) {
+                    children.push(builder.advance());
+                    return Some(SyntaxNode::new(SyntaxKind::Grouping, children));
+                }
+            }
+
+            // все до парной `)` остается в узле, без нее - до конца файла
+            while !builder.at_end() && !builder.check(&[TokenType::RIGHT_PAREN]) {
+                match expression(builder) {
+                    Some(node) => children.push(SyntaxElement::Node(node)),
+                    None => children.push(builder.advance()),
+                }
+            }
+            if builder.check(&[TokenType::RIGHT_PAREN]) {
+                children.push(builder.advance());
+            }
+            Some(SyntaxNode::new(SyntaxKind::Parens, children))
+        }
+        _ => None
+    }
+}
+
+struct Builder<'a> {
+    current: Cell<usize>,
+    depth: Cell<usize>,
+    tokens: &'a [Token],
+}
+
+impl<'a> Builder<'a> {
+    fn new(tokens: &'a [Token], start: usize) -> Self {
+        Builder { current: Cell::new(start), depth: Cell::new(0), tokens }
+    }
+
+    /// как в parser: глубже MAX_NESTING не спускаемся, токен на этой глубине уходит в TooDeep узел
+    fn nested(&self, rule: fn(&Builder) -> Option<SyntaxNode>) -> Option<SyntaxNode> {
+        if self.depth.get() >= MAX_NESTING {
+            if self.at_end() {
+                return None;
+            }
+            return Some(SyntaxNode::new(SyntaxKind::TooDeep, vec![self.advance()]));
+        }
+        self.depth.set(self.depth.get() + 1);
+        let node = rule(self);
+        self.depth.set(self.depth.get() - 1);
+        node
+    }
+
+    /// текущий токен, последний всегда EOF
+    fn peek(&self) -> &Token {
+        &self.tokens[self.current.get().min(self.tokens.len() - 1)]
+    }
+
+    /// забирает текущий токен и двигает оффсет
+    fn advance(&self) -> SyntaxElement {
+        let token = self.peek().clone();
+        self.current.set(self.current.get() + 1);
+        SyntaxElement::Token(token)
+    }
+
+    fn at_end(&self) -> bool {
+        self.peek()._type == TokenType::EOF
+    }
+
+    /// проверяет, что текущий токен одного из типов
+    fn check(&self, token_types: &[TokenType]) -> bool {
+        token_types.contains(&self.peek()._type)
+    }
+}
+
+/// дерево без недостроенных узлов: первая ошибка в терминах parser
+/// так проверяют код `fmt` и диагностики LSP, несколько выражений и одиночные токены подряд - не ошибка
+pub fn check_complete(node: &SyntaxNode) -> Result<(), ParserError> {
+    // переполнение вложенности важнее ошибок вокруг него: из-за него недостроены внешние узлы
+    if node.kind == SyntaxKind::Root {
+        if let Some(token) = too_deep(node) {
+            return Err(ParserError::Default("Expression nesting is too deep.".to_string(), Box::new(token.clone()), 65));
+        }
+    }
+
+    let children = node.children.len();
+    let incomplete = match node.kind {
+        SyntaxKind::Binary => (children < 3).then_some("Expect expression."),
+        SyntaxKind::Unary => (children < 2).then_some("Expect expression."),
+        SyntaxKind::Grouping if !matches!(node.children.get(1), Some(SyntaxElement::Node(_))) => {
+            Some("Expect expression.")
+        }
+        SyntaxKind::Grouping => (children < 3).then_some("Expect ')' after expression."),
+        SyntaxKind::Parens => match node.children.last() {
+            Some(SyntaxElement::Token(token)) if children > 1 && token._type == TokenType::RIGHT_PAREN => None,
+            _ => Some("Expect ')' after expression."),
+        },
+        _ => None
+    };
+    if let Some(message) = incomplete {
+        let token = *node.tokens().last().expect("node without tokens");
+        return Err(ParserError::Default(message.to_string(), Box::new(token.clone()), 65));
+    }
+
+    for child in &node.children {
+        if let SyntaxElement::Node(child) = child {
+            check_complete(child)?;
+        }
+    }
+    Ok(())
+}
+
+fn too_deep(node: &SyntaxNode) -> Option<&Token> {
+    if node.kind == SyntaxKind::TooDeep {
+        return node.tokens().first().copied();
+    }
+    node.children.iter().find_map(|child| match child {
+        SyntaxElement::Node(child) => too_deep(child),
+        SyntaxElement::Token(_) => None,
+    })
+}
@@ -1,4 +1,9 @@
-use std::fmt::{Display, Formatter};
+use crate::interner::Symbol;
+use crate::lexer::number_value;
+use std::borrow::Cow;
+use std::fmt::{Debug, Display, Formatter};
+use std::rc::Rc;
+use thiserror::Error;
 
 #[derive(Debug, Clone)]
 pub struct AST {
@@ -7,8 +12,11 @@ pub struct AST {
 
 #[derive(Debug, Clone)]
 pub struct Tokens {
+    /// 65, если есть ошибки лексера
     pub code: u8,
     pub tokens: Vec<Token>,
+    /// ошибки лексера в порядке исходника, текст с ошибкой в токены не попал
+    pub errors: Vec<LexError>,
 }
 
 #[derive(Debug, Clone)]
@@ -21,53 +29,193 @@ pub enum Expr {
 
 #[derive(Debug, Clone)]
 pub enum ParserError {
-    Default(String, Token, u8)
+    Default(String, Box<Token>, u8),
+    /// ошибка лексера, встреченная парсером при чтении потока токенов
+    Lex(LexError),
 }
 
-#[derive(Debug, Clone, PartialEq)]
+/// ошибка лексера: текст на span пропущен, лексинг продолжается после него
+/// `to_string()` - только сообщение, без строки
+#[derive(Debug, Clone, PartialEq, Error)]
+pub enum LexError {
+    #[error("Unexpected character: {character}")]
+    UnexpectedCharacter { character: char, line: i32, span: Span },
+    #[error("Unterminated string.")]
+    UnterminatedString { line: i32, span: Span },
+    #[error("Unterminated block comment.")]
+    UnterminatedBlockComment { line: i32, span: Span },
+    #[error("Invalid number literal '{literal}': {reason}.")]
+    InvalidNumber { literal: String, reason: String, line: i32, span: Span },
+}
+
+impl LexError {
+    /// строка, на которой начинается ошибочный текст
+    pub fn line(&self) -> i32 {
+        match self {
+            LexError::UnexpectedCharacter { line, .. }
+            | LexError::UnterminatedString { line, .. }
+            | LexError::UnterminatedBlockComment { line, .. }
+            | LexError::InvalidNumber { line, .. } => *line,
+        }
+    }
+
+    pub fn span(&self) -> Span {
+        match self {
+            LexError::UnexpectedCharacter { span, .. }
+            | LexError::UnterminatedString { span, .. }
+            | LexError::UnterminatedBlockComment { span, .. }
+            | LexError::InvalidNumber { span, .. } => *span,
+        }
+    }
+}
+
+/// байтовый диапазон токена в исходнике: `&code[start..end]` дает лексему
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        Span { start, end }
+    }
+}
+
+/// лексема и значение литерала не хранятся, а берутся из `_source` по `_span`:
+/// все токены файла делят один буфер, поэтому лексер не аллоцирует строк на токен
+#[derive(Clone)]
 pub struct Token {
     pub _type: TokenType,
-    pub _string: String,
-    pub _value: Option<String>,
     pub _line: i32,
     pub _column_from: i32,
     pub _column_to: i32,
+    pub _span: Span,
+    pub _trivia: Vec<Trivia>,
+    /// интернированное имя идентификатора или значение строкового литерала
+    pub _symbol: Option<Symbol>,
+    /// исходник целиком
+    pub _source: Rc<str>,
 }
 
-impl Token {
-    pub fn new_char(_type: TokenType, _char: char, _line: i32, _column: i32) -> Self {
-        Token {
-            _type,
-            _value: None,
-            _string: _char.to_string(),
-            _line,
-            _column_from: _column,
-            _column_to: _column,
-        }
-    }
+/// то, что лексер не превращает в токены, но сохраняет за следующим токеном
+/// склейка `text()` trivia и лексем дает исходник байт в байт
+#[derive(Clone)]
+pub struct Trivia {
+    pub kind: TriviaKind,
+    pub span: Span,
+    pub source: Rc<str>,
+}
+
+/// в обычном режиме лексер сохраняет только DocComment, остальное - в lossless режиме
+#[derive(Debug, Clone, PartialEq)]
+pub enum TriviaKind {
+    /// пробелы, табы и переводы строк подряд
+    Whitespace,
+    /// `// ...` без перевода строки
+    LineComment,
+    /// `/* ... */`, в т.ч. вложенный или незакрытый
+    BlockComment,
+    /// `/// ...`
+    DocComment,
+    /// текст, на котором лексер сообщил об ошибке
+    Skipped,
+}
 
-    pub fn new_content(_type: TokenType, _string: String,
-                       _value: String, _line: i32, _column_from: i32, _column_to: i32) -> Self {
+impl Token {
+    pub fn new(_type: TokenType, _source: &Rc<str>, _span: Span,
+               _line: i32, _column_from: i32, _column_to: i32) -> Self {
         Token {
             _type,
-            _string,
-            _value: Some(_value),
             _line,
             _column_from,
             _column_to,
+            _span,
+            _trivia: Vec::new(),
+            _symbol: None,
+            _source: Rc::clone(_source),
         }
     }
 
-    pub fn new(_type: TokenType, _string: String, _line: i32, _column_from: i32, _column_to: i32) -> Self {
-        Token {
-            _type,
-            _string,
-            _value: None,
-            _line,
-            _column_from,
-            _column_to,
+    /// текст токена в исходнике, у EOF - пустая строка
+    pub fn lexeme(&self) -> &str {
+        &self._source[self._span.start..self._span.end]
+    }
+
+    /// значение литерала: строка без кавычек или число в каноничном виде `N.0`,
+    /// считается при каждом вызове, у остальных токенов - None
+    pub fn value(&self) -> Option<Cow<'_, str>> {
+        let lexeme = self.lexeme();
+        match self._type {
+            TokenType::STRING => Some(Cow::Borrowed(&lexeme[1..lexeme.len() - 1])),
+            TokenType::NUMBER => Some(Cow::Owned(number_value(lexeme))),
+            _ => None,
         }
     }
+
+    /// документация из `///` комментариев перед токеном без `///` и первого пробела,
+    /// строки склеены через `\n`
+    pub fn doc_comment(&self) -> Option<String> {
+        let lines: Vec<&str> = self._trivia.iter()
+            .filter(|trivia| trivia.kind == TriviaKind::DocComment)
+            .map(|trivia| {
+                let text = &trivia.text()[3..];
+                text.strip_prefix(' ').unwrap_or(text)
+            })
+            .collect();
+        if lines.is_empty() { None } else { Some(lines.join("\n")) }
+    }
+}
+
+impl Trivia {
+    pub fn text(&self) -> &str {
+        &self.source[self.span.start..self.span.end]
+    }
+}
+
+/// исходник не сравнивается и не печатается целиком, только лексема
+impl PartialEq for Token {
+    fn eq(&self, other: &Self) -> bool {
+        self._type == other._type
+            && self.lexeme() == other.lexeme()
+            && self._line == other._line
+            && self._column_from == other._column_from
+            && self._column_to == other._column_to
+            && self._span == other._span
+            && self._trivia == other._trivia
+            && self._symbol == other._symbol
+    }
+}
+
+impl Debug for Token {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Token")
+            .field("_type", &self._type)
+            .field("_lexeme", &self.lexeme())
+            .field("_line", &self._line)
+            .field("_column_from", &self._column_from)
+            .field("_column_to", &self._column_to)
+            .field("_span", &self._span)
+            .field("_trivia", &self._trivia)
+            .field("_symbol", &self._symbol)
+            .finish()
+    }
+}
+
+impl PartialEq for Trivia {
+    fn eq(&self, other: &Self) -> bool {
+        self.kind == other.kind && self.span == other.span && self.text() == other.text()
+    }
+}
+
+impl Debug for Trivia {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Trivia")
+            .field("kind", &self.kind)
+            .field("text", &self.text())
+            .field("span", &self.span)
+            .finish()
+    }
 }
 
 impl Display for Expr {
@@ -87,8 +235,8 @@ impl Display for Token {
             f,
             "{} {} {}", // {?:} добавляет кавычки
             self._type.to_string(),
-            self._string,
-            self._value.clone().unwrap_or("null".to_string())
+            self.lexeme(),
+            self.value().unwrap_or(Cow::Borrowed("null"))
         )
     }
 }
@@ -0,0 +1,298 @@
+use crate::cst::{build, check_complete, SyntaxElement, SyntaxKind, SyntaxNode};
+use crate::domain::{KeywordType, ParserError, Token, TokenType, Tokens, TriviaKind};
+
+pub const DEFAULT_WIDTH: usize = 80;
+
+const INDENT: usize = 4;
+
+/// форматирует файл по CST: пробелы вокруг бинарных операторов, отступы внутри `{ }`,
+/// перенос длинных выражений по операторам с отступом, комментарии сохраняются
+/// tokens должны быть получены через `lexer::tokenize_lossless` без ошибок
+pub fn format(tokens: Tokens, width: usize) -> Result<String, ParserError> {
+    let tree = build(tokens);
+    check_complete(&tree)?;
+
+    let mut printer = Printer {
+        out: String::new(),
+        width,
+        column: 0,
+        indent: 0,
+        item_indent: 0,
+        item_start: true,
+        comment_indent: None,
+    };
+
+    let mut depth: usize = 0;
+    let mut previous: Option<&SyntaxElement> = None;
+    for child in &tree.children {
+        let first = first_token(child);
+        if first._type == TokenType::RIGHT_BRACE && depth > 0 {
+            // комментарии перед `}` относятся к содержимому блока
+            printer.comment_indent = Some(depth * INDENT);
+            depth -= 1;
+        }
+        printer.item_indent = depth * INDENT;
+        printer.indent = printer.item_indent + INDENT;
+        printer.item_start = true;
+
+        let sep = match previous {
+            _ if first._type == TokenType::EOF => Sep::None,
+            None => Sep::None,
+            Some(previous) => top_level_sep(last_token(previous), first, depth),
+        };
+
+        match child {
+            SyntaxElement::Node(node) if node.kind != SyntaxKind::Error => print_node(&mut printer, node, sep),
+            _ => printer.token(first, sep),
+        }
+
+        if first._type == TokenType::LEFT_BRACE {
+            depth += 1;
+        }
+        previous = Some(child);
+    }
+
+    let mut out = printer.out.trim_end().to_string();
+    if !out.is_empty() {
+        out.push('\n');
+    }
+    Ok(out)
+}
+
+/// разделитель перед токеном
+#[derive(Clone, Copy)]
+enum Sep {
+    None,
+    Space,
+    /// перевод строки и отступ
+    Newline(usize),
+}
+
+/// разделитель между соседними элементами верхнего уровня: выражений и одиночных токенов `{ } ; ,`
+fn top_level_sep(previous: &Token, next: &Token, depth: usize) -> Sep {
+    let newline = Sep::Newline(depth * INDENT);
+
+    match (&previous._type, &next._type) {
+        (TokenType::SEMICOLON | TokenType::LEFT_BRACE, _) => newline,
+        (TokenType::RIGHT_BRACE, TokenType::KEYWORD(KeywordType::ELSE)) => Sep::Space,
+        (TokenType::RIGHT_BRACE, _) | (_, TokenType::RIGHT_BRACE) => newline,
+        _ => match inline_sep(previous, next) {
+            Sep::Space if source_newlines(next) > 0 => newline,
+            sep => sep,
+        },
+    }
+}
+
+/// разделитель внутри строки: между элементами `( ... )` и на верхнем уровне, если перенос не нужен
+fn inline_sep(previous: &Token, next: &Token) -> Sep {
+    let glued = next._trivia.is_empty();
+
+    match (&previous._type, &next._type) {
+        (TokenType::LEFT_PAREN | TokenType::DOT, _) => Sep::None,
+        (_, TokenType::SEMICOLON | TokenType::COMMA | TokenType::DOT | TokenType::RIGHT_PAREN) => Sep::None,
+        // вызов `foo(1)` остается слитным
+        (TokenType::IDENTIFIER | TokenType::RIGHT_PAREN, TokenType::LEFT_PAREN) if glued => Sep::None,
+        _ => Sep::Space,
+    }
+}
+
+fn print_node(printer: &mut Printer, node: &SyntaxNode, sep: Sep) {
+    match node.kind {
+        SyntaxKind::Binary if printer.column + flat_width(node) > printer.width => {
+            print_broken_binary(printer, node, sep)
+        }
+        SyntaxKind::Binary => {
+            let (left, operator, right) = (node_at(node, 0), token_at(node, 1), node_at(node, 2));
+            print_node(printer, left, sep);
+            printer.token(operator, Sep::Space);
+            print_node(printer, right, Sep::Space);
+        }
+        SyntaxKind::Unary => {
+            printer.token(token_at(node, 0), sep);
+            print_node(printer, node_at(node, 1), Sep::None);
+        }
+        SyntaxKind::Grouping => {
+            printer.token(token_at(node, 0), sep);
+            print_node(printer, node_at(node, 1), Sep::None);
+            printer.token(token_at(node, 2), Sep::None);
+        }
+        SyntaxKind::Parens => {
+            printer.token(token_at(node, 0), sep);
+            for pair in node.children.windows(2) {
+                let sep = inline_sep(last_token(&pair[0]), first_token(&pair[1]));
+                match &pair[1] {
+                    SyntaxElement::Node(child) => print_node(printer, child, sep),
+                    SyntaxElement::Token(token) => printer.token(token, sep),
+                }
+            }
+        }
+        _ => {
+            for token in node.tokens() {
+                printer.token(token, sep);
+            }
+        }
+    }
+}
+
+/// цепочка `a + b - c` не влезает в ширину: каждый оператор переносится на новую строку
+fn print_broken_binary(printer: &mut Printer, node: &SyntaxNode, sep: Sep) {
+    let mut operands = Vec::new();
+    let mut operators = Vec::new();
+    let mut current = node;
+    while current.kind == SyntaxKind::Binary
+        && precedence(&token_at(current, 1)._type) == precedence(&token_at(node, 1)._type) {
+        operators.push(token_at(current, 1));
+        operands.push(node_at(current, 2));
+        current = node_at(current, 0);
+    }
+    operands.push(current);
+    operands.reverse();
+    operators.reverse();
+
+    let indent = printer.indent;
+    print_node(printer, operands[0], sep);
+    for (operator, operand) in operators.into_iter().zip(operands.into_iter().skip(1)) {
+        printer.token(operator, Sep::Newline(indent));
+        print_node(printer, operand, Sep::Space);
+    }
+}
+
+fn precedence(token_type: &TokenType) -> u8 {
+    match token_type {
+        TokenType::BANG_EQUAL | TokenType::EQUAL_EQUAL => 1,
+        TokenType::GREATER | TokenType::GREATER_EQUAL | TokenType::LESS | TokenType::LESS_EQUAL => 2,
+        TokenType::MINUS | TokenType::PLUS => 3,
+        _ => 4
+    }
+}
+
+/// ширина узла, если напечатать его в одну строку
+fn flat_width(node: &SyntaxNode) -> usize {
+    match node.kind {
+        SyntaxKind::Binary => {
+            flat_width(node_at(node, 0)) + token_at(node, 1).lexeme().chars().count() + flat_width(node_at(node, 2)) + 2
+        }
+        _ => node.tokens().iter().map(|token| token.lexeme().chars().count()).sum()
+    }
+}
+
+fn node_at(node: &SyntaxNode, index: usize) -> &SyntaxNode {
+    match &node.children[index] {
+        SyntaxElement::Node(node) => node,
+        SyntaxElement::Token(_) => panic!("expected node at {}", index),
+    }
+}
+
+fn token_at(node: &SyntaxNode, index: usize) -> &Token {
+    match &node.children[index] {
+        SyntaxElement::Token(token) => token,
+        SyntaxElement::Node(_) => panic!("expected token at {}", index),
+    }
+}
+
+fn first_token(element: &SyntaxElement) -> &Token {
+    match element {
+        SyntaxElement::Node(node) => node.tokens()[0],
+        SyntaxElement::Token(token) => token,
+    }
+}
+
+fn last_token(element: &SyntaxElement) -> &Token {
+    match element {
+        SyntaxElement::Node(node) => node.tokens().last().expect("node without tokens"),
+        SyntaxElement::Token(token) => token,
+    }
+}
+
+/// число переводов строк в пробелах перед токеном
+fn source_newlines(token: &Token) -> usize {
+    token._trivia.iter()
+        .filter(|trivia| trivia.kind == TriviaKind::Whitespace)
+        .map(|trivia| trivia.text().matches('\n').count())
+        .sum()
+}
+
+struct Printer {
+    out: String,
+    width: usize,
+    /// длина текущей строки в символах
+    column: usize,
+    /// отступ для переносов внутри выражения
+    indent: usize,
+    /// отступ строки, с которой начинается текущий элемент верхнего уровня
+    item_indent: usize,
+    /// следующий токен - первый в элементе верхнего уровня
+    item_start: bool,
+    /// отступ комментариев на своей строке перед следующим токеном, если он не совпадает с отступом токена
+    comment_indent: Option<usize>,
+}
+
+impl Printer {
+    /// печатает комментарии перед токеном, затем разделитель и сам токен
+    fn token(&mut self, token: &Token, sep: Sep) {
+        let indent = match sep {
+            Sep::Newline(indent) => indent,
+            _ if self.item_start => self.item_indent,
+            _ => self.indent,
+        };
+        self.item_start = false;
+        // комментарий сразу после `{` - содержимое блока, он переносится внутрь, как `{ y }`
+        let block_content = matches!(sep, Sep::Newline(_)) && self.out.trim_end().ends_with('{');
+        let comment_indent = self.comment_indent.take().unwrap_or(indent);
+
+        // переводы строк в исходнике после предыдущего токена или комментария
+        let mut newlines = 0;
+        // после комментария нужен перенос, а не пробел
+        let mut comment_break = None;
+
+        for trivia in &token._trivia {
+            match trivia.kind {
+                TriviaKind::Whitespace => newlines += trivia.text().matches('\n').count(),
+                TriviaKind::LineComment | TriviaKind::DocComment | TriviaKind::BlockComment => {
+                    if !self.out.is_empty() {
+                        // комментарий на своей строке остается на своей строке, в конце строки - в конце
+                        if newlines > 0 || comment_break == Some(true) || (block_content && comment_break.is_none()) {
+                            self.newline(comment_indent, newlines > 1);
+                        } else {
+                            self.write(" ");
+                        }
+                    }
+                    self.write(trivia.text());
+                    newlines = 0;
+                    comment_break = Some(trivia.kind != TriviaKind::BlockComment);
+                }
+                TriviaKind::Skipped => self.write(trivia.text()),
+            }
+        }
+
+        match (comment_break, sep) {
+            (Some(true), _) => self.newline(indent, newlines > 1),
+            (Some(false), Sep::Newline(_)) if block_content && token._type == TokenType::RIGHT_BRACE => {
+                self.newline(indent, false)
+            }
+            (Some(false), _) if newlines > 0 => self.newline(indent, newlines > 1),
+            (Some(false), _) => self.write(" "),
+            (None, Sep::None) => {}
+            (None, Sep::Space) => self.write(" "),
+            (None, Sep::Newline(indent)) => self.newline(indent, newlines > 1),
+        }
+        self.write(token.lexeme());
+    }
+
+    /// перевод строки с отступом, blank - с одной пустой строкой перед ней
+    fn newline(&mut self, indent: usize, blank: bool) {
+        let trimmed = self.out.trim_end_matches(' ').len();
+        self.out.truncate(trimmed);
+        self.out.push('\n');
+        if blank {
+            self.out.push('\n');
+        }
+        self.out.push_str(&" ".repeat(indent));
+        self.column = indent;
+    }
+
+    fn write(&mut self, text: &str) {
+        self.out.push_str(text);
+        self.column += text.chars().count();
+    }
+}
@@ -0,0 +1,114 @@
+use crate::domain::{Span, TokenType, Tokens, TriviaKind};
+use serde_json::{json, Value};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum HighlightKind {
+    Keyword,
+    String,
+    Number,
+    /// объявлений в языке нет, поэтому любой идентификатор - обращение к глобальному имени
+    Global,
+    Comment,
+}
+
+impl HighlightKind {
+    pub fn to_string(&self) -> &'static str {
+        match self {
+            HighlightKind::Keyword => "keyword",
+            HighlightKind::String => "string",
+            HighlightKind::Number => "number",
+            HighlightKind::Global => "global",
+            HighlightKind::Comment => "comment",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Highlight {
+    pub kind: HighlightKind,
+    pub span: Span,
+    pub line: i32,
+}
+
+/// раскрашенные участки в порядке исходника, tokens - из `lexer::tokenize_lossless`
+pub fn classify(tokens: &Tokens) -> Vec<Highlight> {
+    let mut highlights = Vec::new();
+
+    for token in &tokens.tokens {
+        // строка trivia: строка токена минус переводы строк между trivia и токеном
+        let mut line = token._line - token._trivia.iter()
+            .map(|trivia| trivia.text().matches('\n').count() as i32)
+            .sum::<i32>();
+        for trivia in &token._trivia {
+            if matches!(trivia.kind, TriviaKind::LineComment | TriviaKind::BlockComment | TriviaKind::DocComment) {
+                highlights.push(Highlight { kind: HighlightKind::Comment, span: trivia.span, line });
+            }
+            line += trivia.text().matches('\n').count() as i32;
+        }
+
+        let kind = match token._type {
+            TokenType::KEYWORD(_) => HighlightKind::Keyword,
+            TokenType::STRING => HighlightKind::String,
+            TokenType::NUMBER => HighlightKind::Number,
+            TokenType::IDENTIFIER => HighlightKind::Global,
+            _ => continue,
+        };
+        highlights.push(Highlight { kind, span: token._span, line: token._line });
+    }
+
+    highlights
+}
+
+/// `[{"kind": "keyword", "line": 1, "start": 0, "end": 3}, ...]`, start/end - байтовые оффсеты
+pub fn to_json(highlights: &[Highlight]) -> String {
+    let spans: Vec<Value> = highlights.iter()
+        .map(|highlight| json!({
+            "kind": highlight.kind.to_string(),
+            "line": highlight.line,
+            "start": highlight.span.start,
+            "end": highlight.span.end,
+        }))
+        .collect();
+    Value::Array(spans).to_string()
+}
+
+/// самодостаточная html страница с раскрашенным исходником
+pub fn to_html(code: &str, highlights: &[Highlight], title: &str) -> String {
+    let mut body = String::new();
+    let mut offset = 0;
+    for highlight in highlights {
+        body.push_str(&escape(&code[offset..highlight.span.start]));
+        body.push_str(&format!("<span class=\"{}\">{}</span>",
+                               highlight.kind.to_string(),
+                               escape(&code[highlight.span.start..highlight.span.end])));
+        offset = highlight.span.end;
+    }
+    body.push_str(&escape(&code[offset..]));
+
+    format!(r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>{}</title>
+<style>
+pre {{ background: #fafafa; color: #383a42; padding: 1em; }}
+.keyword {{ color: #a626a4; font-weight: bold; }}
+.string {{ color: #50a14f; }}
+.number {{ color: #986801; }}
+.global {{ color: #4078f2; }}
+.comment {{ color: #a0a1a7; font-style: italic; }}
+</style>
+</head>
+<body>
+<pre><code>{}</code></pre>
+</body>
+</html>
+"#, escape(title), body)
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
@@ -0,0 +1,298 @@
+use crate::cst::{build, build_top_level, SyntaxElement, SyntaxKind, SyntaxNode};
+use crate::domain::{LexError, Span, Token, TokenType, Tokens};
+use crate::interner::Interner;
+use crate::lexer::{Checkpoint, Lexer, LOOKAHEAD};
+use std::ops::Range;
+use std::rc::Rc;
+
+/// замена байтового диапазона span старого текста на text
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextEdit {
+    pub span: Span,
+    pub text: String,
+}
+
+/// документ редактора: lossless токены, ошибки лексера и CST,
+/// после правки лексер и построитель дерева перезапускаются только вокруг нее
+/// результат всегда тот же, что у `cst::build(lexer::tokenize_lossless(text))`
+pub struct Document {
+    source: Rc<str>,
+    tokens: Vec<Token>,
+    /// состояние лексера после каждого токена
+    checkpoints: Vec<Checkpoint>,
+    errors: Vec<LexError>,
+    tree: SyntaxNode,
+    /// индекс первого токена каждого ребенка Root, последний ребенок - EOF
+    starts: Vec<usize>,
+    /// общий для всех перезапусков лексера, хранит и строки удаленных токенов
+    interner: Interner,
+    /// размер интернера, после которого он пересобирается из живых токенов
+    compact_at: usize,
+}
+
+/// меньше этого интернер не пересобирается: иначе короткий документ пересобирал бы его на каждой правке
+const MIN_COMPACT_AT: usize = 64;
+
+impl Document {
+    pub fn new(code: String) -> Self {
+        let source: Rc<str> = Rc::from(code);
+        let lexed = lex(&source, Checkpoint::START, Interner::default(), |_| None);
+        let tree = build(Tokens { code: 0, tokens: lexed.tokens.clone(), errors: Vec::new() });
+        let mut starts = Vec::new();
+        let mut start = 0;
+        for child in &tree.children {
+            starts.push(start);
+            start += element_tokens(child);
+        }
+
+        Document {
+            source,
+            tokens: lexed.tokens,
+            checkpoints: lexed.checkpoints,
+            errors: lexed.errors,
+            tree,
+            starts,
+            interner: lexed.interner,
+            compact_at: MIN_COMPACT_AT,
+        }
+    }
+
+    pub fn text(&self) -> &str {
+        &self.source
+    }
+
+    pub fn tree(&self) -> &SyntaxNode {
+        &self.tree
+    }
+
+    /// токены в том виде, в каком их отдает `lexer::tokenize_lossless`
+    pub fn tokens(&self) -> &[Token] {
+        &self.tokens
+    }
+
+    pub fn errors(&self) -> &[LexError] {
+        &self.errors
+    }
+
+    #[cfg(test)]
+    pub fn interned(&self) -> usize {
+        self.interner.len()
+    }
+
+    /// применяет правку и возвращает индексы заново полученных от лексера токенов,
+    /// остальные токены и узлы верхнего уровня переиспользуются со сдвинутыми позициями
+    pub fn edit(&mut self, edit: &TextEdit) -> Range<usize> {
+        let span = edit.span;
+        let mut code = String::with_capacity(self.source.len() - (span.end - span.start) + edit.text.len());
+        code.push_str(&self.source[..span.start]);
+        code.push_str(&edit.text);
+        code.push_str(&self.source[span.end..]);
+        let source: Rc<str> = Rc::from(code);
+        let delta = edit.text.len() as isize - (span.end - span.start) as isize;
+
+        // токен не меняется, если правка дальше LOOKAHEAD символов от его конца
+        let mut kept = self.tokens.partition_point(|token| token._type != TokenType::EOF && token._span.end <= span.start);
+        while kept > 0 && self.source[self.tokens[kept - 1]._span.end..span.start].chars().nth(LOOKAHEAD - 1).is_none() {
+            kept -= 1;
+        }
+        let from = if kept == 0 { Checkpoint::START } else { self.checkpoints[kept - 1] };
+
+        // лексер дальше идет как раньше, если после токена за правкой его состояние совпало со старым
+        let old_checkpoints = &self.checkpoints[..self.checkpoints.len() - 1];
+        let interner = std::mem::take(&mut self.interner);
+        let lexed = lex(&source, from, interner, |checkpoint| {
+            let old_offset = checkpoint.offset.checked_add_signed(-delta).filter(|offset| *offset >= span.end)?;
+            let index = old_checkpoints.binary_search_by_key(&old_offset, |old| old.offset).ok()?;
+            (old_checkpoints[index].col == checkpoint.col).then_some(index)
+        });
+        let line_delta = match lexed.resync {
+            Some(index) => lexed.checkpoints.last().expect("resync after a token").line - self.checkpoints[index].line,
+            None => 0,
+        };
+
+        // ошибки: до перезапуска лексера - как были, за точкой совпадения - сдвинутые
+        let mut errors: Vec<LexError> = self.errors.iter()
+            .filter(|error| error.span().start < from.offset)
+            .cloned()
+            .collect();
+        errors.extend(lexed.errors);
+        if let Some(index) = lexed.resync {
+            let resync_offset = self.checkpoints[index].offset;
+            errors.extend(self.errors.iter()
+                .filter(|error| error.span().start >= resync_offset)
+                .map(|error| shift_error(error, delta, line_delta)));
+        }
+
+        // токены: префикс, заново разобранное окно и сдвинутый хвост
+        let tail_from = lexed.resync.map_or(self.tokens.len(), |index| index + 1);
+        let window = kept..kept + lexed.tokens.len();
+        let mut tokens = std::mem::take(&mut self.tokens);
+        let mut tail = tokens.split_off(tail_from);
+        tokens.truncate(kept);
+        tokens.iter_mut().for_each(|token| rebase(token, &source, 0, 0));
+        tail.iter_mut().for_each(|token| rebase(token, &source, delta, line_delta));
+        tokens.extend(lexed.tokens);
+        tokens.extend(tail);
+
+        let mut checkpoints = std::mem::take(&mut self.checkpoints);
+        let tail = checkpoints.split_off(tail_from);
+        checkpoints.truncate(kept);
+        checkpoints.extend(lexed.checkpoints);
+        checkpoints.extend(tail.into_iter().map(|checkpoint| Checkpoint {
+            offset: checkpoint.offset.saturating_add_signed(delta),
+            line: checkpoint.line + line_delta,
+            col: checkpoint.col,
+        }));
+
+        // дерево: узел верхнего уровня переиспользуется, если не изменились его токены
+        // и токен сразу после него; старый индекс токена в хвосте = новый + shift
+        let shift = tail_from as isize - window.end as isize;
+        let old_starts = std::mem::take(&mut self.starts);
+        let mut old_children = std::mem::take(&mut self.tree.children).into_iter();
+        let mut children = Vec::new();
+        let mut starts = Vec::new();
+
+        let reused = old_starts.windows(2).take_while(|bounds| bounds[1] < kept).count();
+        for (mut element, start) in old_children.by_ref().take(reused).zip(&old_starts) {
+            rebase_element(&mut element, &source, 0, 0);
+            children.push(element);
+            starts.push(*start);
+        }
+
+        let mut index = old_starts[reused];
+        loop {
+            if index >= window.end {
+                let old_index = index.saturating_add_signed(shift);
+                if let Ok(old_child) = old_starts.binary_search(&old_index) {
+                    let skipped = old_child - reused;
+                    for (mut element, start) in old_children.skip(skipped).zip(&old_starts[old_child..]) {
+                        rebase_element(&mut element, &source, delta, line_delta);
+                        children.push(element);
+                        starts.push(start.saturating_add_signed(-shift));
+                    }
+                    break;
+                }
+            }
+
+            starts.push(index);
+            if tokens[index]._type == TokenType::EOF {
+                children.push(SyntaxElement::Token(tokens[index].clone()));
+                break;
+            }
+            let (node, next) = build_top_level(&tokens, index);
+            children.push(SyntaxElement::Node(node));
+            index = next;
+        }
+
+        self.source = source;
+        self.tokens = tokens;
+        self.checkpoints = checkpoints;
+        self.errors = errors;
+        self.tree = SyntaxNode { kind: SyntaxKind::Root, children };
+        self.starts = starts;
+        self.interner = lexed.interner;
+        if self.interner.len() > self.compact_at {
+            self.compact();
+        }
+        window
+    }
+
+    /// пересобирает интернер из строк живых токенов, символы токенов и дерева меняются на новые
+    fn compact(&mut self) {
+        let old = std::mem::take(&mut self.interner);
+        let interner = &mut self.interner;
+        let mut remap = |token: &mut Token| {
+            token._symbol = token._symbol.map(|symbol| interner.intern(old.resolve(symbol)));
+        };
+        self.tokens.iter_mut().for_each(&mut remap);
+        self.tree.children.iter_mut().for_each(|child| for_each_token(child, &mut remap));
+        self.compact_at = (2 * self.interner.len()).max(MIN_COMPACT_AT);
+    }
+}
+
+/// результат лексера с одного состояния до EOF или до совпадения со старым разбором
+struct Lexed {
+    tokens: Vec<Token>,
+    checkpoints: Vec<Checkpoint>,
+    errors: Vec<LexError>,
+    /// индекс старого токена, после которого состояние лексера совпало
+    resync: Option<usize>,
+    interner: Interner,
+}
+
+fn lex(source: &Rc<str>, from: Checkpoint, interner: Interner, mut resync: impl FnMut(Checkpoint) -> Option<usize>) -> Lexed {
+    let mut lexer = Lexer::resume(Rc::clone(source), from, interner);
+    let mut lexed = Lexed { tokens: Vec::new(), checkpoints: Vec::new(), errors: Vec::new(), resync: None, interner: Interner::default() };
+
+    while let Some(item) = lexer.next() {
+        match item {
+            Ok(token) => {
+                let eof = token._type == TokenType::EOF;
+                let checkpoint = lexer.checkpoint();
+                lexed.tokens.push(token);
+                lexed.checkpoints.push(checkpoint);
+                if !eof {
+                    lexed.resync = resync(checkpoint);
+                    if lexed.resync.is_some() {
+                        break;
+                    }
+                }
+            }
+            Err(error) => lexed.errors.push(error),
+        }
+    }
+
+    lexed.interner = lexer.into_interner();
+    lexed
+}
+
+fn element_tokens(element: &SyntaxElement) -> usize {
+    match element {
+        SyntaxElement::Node(node) => node.children.iter().map(element_tokens).sum(),
+        SyntaxElement::Token(_) => 1,
+    }
+}
+
+fn shift(span: Span, delta: isize) -> Span {
+    Span::new(span.start.saturating_add_signed(delta), span.end.saturating_add_signed(delta))
+}
+
+/// переносит токен в новый исходник
+fn rebase(token: &mut Token, source: &Rc<str>, delta: isize, line_delta: i32) {
+    token._source = Rc::clone(source);
+    token._span = shift(token._span, delta);
+    token._line += line_delta;
+    for trivia in &mut token._trivia {
+        trivia.source = Rc::clone(source);
+        trivia.span = shift(trivia.span, delta);
+    }
+}
+
+fn rebase_element(element: &mut SyntaxElement, source: &Rc<str>, delta: isize, line_delta: i32) {
+    match element {
+        SyntaxElement::Node(node) => node.children.iter_mut()
+            .for_each(|child| rebase_element(child, source, delta, line_delta)),
+        SyntaxElement::Token(token) => rebase(token, source, delta, line_delta),
+    }
+}
+
+fn for_each_token(element: &mut SyntaxElement, f: &mut impl FnMut(&mut Token)) {
+    match element {
+        SyntaxElement::Node(node) => node.children.iter_mut().for_each(|child| for_each_token(child, f)),
+        SyntaxElement::Token(token) => f(token),
+    }
+}
+
+fn shift_error(error: &LexError, delta: isize, line_delta: i32) -> LexError {
+    let mut error = error.clone();
+    match &mut error {
+        LexError::UnexpectedCharacter { line, span, .. }
+        | LexError::UnterminatedString { line, span }
+        | LexError::UnterminatedBlockComment { line, span }
+        | LexError::InvalidNumber { line, span, .. } => {
+            *line += line_delta;
+            *span = shift(*span, delta);
+        }
+    }
+    error
+}
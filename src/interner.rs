@@ -0,0 +1,37 @@
+use std::collections::HashMap;
+use std::rc::Rc;
+
+/// id строки в интернере: сравнение и хеширование - по числу, а не по строке
+/// символы из разных интернеров сравнивать нельзя
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Symbol(u32);
+
+/// строки живут столько же, сколько владелец интернера: лексер или документ редактора
+#[derive(Debug, Default)]
+pub struct Interner {
+    symbols: HashMap<Rc<str>, Symbol>,
+    strings: Vec<Rc<str>>,
+}
+
+impl Interner {
+    /// возвращает символ строки, одинаковые строки хранятся один раз
+    pub fn intern(&mut self, string: &str) -> Symbol {
+        if let Some(symbol) = self.symbols.get(string) {
+            return *symbol;
+        }
+
+        let string: Rc<str> = Rc::from(string);
+        let symbol = Symbol(self.strings.len() as u32);
+        self.strings.push(Rc::clone(&string));
+        self.symbols.insert(string, symbol);
+        symbol
+    }
+
+    pub fn resolve(&self, symbol: Symbol) -> &str {
+        &self.strings[symbol.0 as usize]
+    }
+
+    pub fn len(&self) -> usize {
+        self.strings.len()
+    }
+}
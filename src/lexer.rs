@@ -1,238 +1,494 @@
-use crate::domain::{KeywordType, Token, TokenType, Tokens};
+use crate::domain::{KeywordType, LexError, Span, Token, TokenType, Tokens, Trivia, TriviaKind};
+use crate::interner::Interner;
 use lazy_static::lazy_static;
+use std::borrow::Cow;
 use std::collections::HashMap;
-use std::io::Write;
-use std::iter::Peekable;
-use std::str::Chars;
-use std::{fs, io};
+use std::rc::Rc;
+use unicode_xid::UnicodeXID;
 
 pub fn tokenize_code(code: String) -> Tokens {
-    process_tokens(code)
+    process_tokens(Lexer::new(code))
 }
 
-pub fn tokenize(filename: &String) -> Tokens {
-    let file_contents = fs::read_to_string(filename).unwrap_or_else(|ex| {
-        writeln!(io::stderr(), "Failed to read file {}, ex: {}", *filename, ex).unwrap();
-        String::new()
-    });
+/// lossless режим: пробелы, комментарии и ошибочный текст сохраняются в `_trivia`
+/// следующего токена (хвост файла - у EOF), так что из токенов собирается исходник байт в байт
+pub fn tokenize_lossless(code: String) -> Tokens {
+    process_tokens(Lexer::lossless(code))
+}
+
+/// собирает все токены и ошибки
+fn process_tokens(lexer: Lexer) -> Tokens {
+    let mut result: u8 = 0;
+    let mut tokens: Vec<Token> = Vec::new();
+    let mut errors: Vec<LexError> = Vec::new();
+
+    for token in lexer {
+        match token {
+            Ok(token) => tokens.push(token),
+            Err(error) => {
+                errors.push(error);
+                result = 65;
+            }
+        }
+    }
 
-    process_tokens(file_contents)
+    Tokens { tokens, code : result, errors }
 }
 
-fn process_tokens(code: String) -> Tokens {
-    let mut line = 1;
-    let mut col = 0;
+/// сколько символов после конца токена лексер может прочитать, решая, где токен кончается:
+/// `1e+` - это NUMBER, IDENTIFIER и PLUS, пока за ними не появится цифра
+pub const LOOKAHEAD: usize = 3;
 
-    let mut result: u8 = 0;
-    let mut tokens = Vec::new();
-    let mut data = code.chars().peekable();
-
-    if !code.is_empty() {
-        while let Some(c) = data.next() {
-            col += 1;
-            match c {
-                '(' => tokens.push(Token::new_char(TokenType::LEFT_PAREN, c, line, col)),
-                ')' => tokens.push(Token::new_char(TokenType::RIGHT_PAREN, c, line, col)),
-                '{' => tokens.push(Token::new_char(TokenType::LEFT_BRACE, c, line, col)),
-                '}' => tokens.push(Token::new_char(TokenType::RIGHT_BRACE, c, line, col)),
-                ',' => tokens.push(Token::new_char(TokenType::COMMA, c, line, col)),
-                '.' => tokens.push(Token::new_char(TokenType::DOT, c, line, col)),
-                '+' => tokens.push(Token::new_char(TokenType::PLUS, c, line, col)),
-                '-' => tokens.push(Token::new_char(TokenType::MINUS, c, line, col)),
-                ';' => tokens.push(Token::new_char(TokenType::SEMICOLON, c, line, col)),
-                '*' => tokens.push(Token::new_char(TokenType::STAR, c, line, col)),
-                '=' => {
-                    let (_type, string, cur_col) =
-                        composite_token(&mut data, '=', '=', col, TokenType::EQUAL_EQUAL, TokenType::EQUAL);
-                    tokens.push(Token::new(_type, string, line, col, cur_col))
-                }
-                '!' => {
-                    let (_type, string, cur_col) =
-                        composite_token(&mut data, '!', '=', col, TokenType::BANG_EQUAL, TokenType::BANG);
-                    tokens.push(Token::new(_type, string, line, col, cur_col))
-                }
-                '<' => {
-                    let (_type, string, cur_col) =
-                        composite_token(&mut data, '<', '=', col, TokenType::LESS_EQUAL, TokenType::LESS);
-                    tokens.push(Token::new(_type, string, line, col, cur_col))
-                }
-                '>' => {
-                    let (_type, string, cur_col) =
-                        composite_token(&mut data, '>', '=', col, TokenType::GREATER_EQUAL, TokenType::GREATER);
-                    tokens.push(Token::new(_type, string, line, col, cur_col))
-                }
-                '/' => {
-                    if let Some(&next) = data.peek() {
-                        if next == '/' {
-                            skip_while(&mut data, |token| token != '\n');
-                            continue;
+/// состояние лексера сразу после токена, с него можно продолжить разбор того же текста
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Checkpoint {
+    pub offset: usize,
+    pub line: i32,
+    pub col: i32,
+}
+
+impl Checkpoint {
+    /// состояние до первого символа
+    pub const START: Checkpoint = Checkpoint { offset: 0, line: 1, col: 0 };
+}
+
+/// лексер как итератор: токены читаются по одному, ошибка возвращается значением `LexError`,
+/// а текст с ошибкой пропускается. Последний элемент - всегда EOF
+/// лексемы - срезы общего буфера `source` без копирования
+pub struct Lexer {
+    source: Rc<str>,
+    /// всегда указывает на следующий непрочитанный байт
+    offset: usize,
+    line: i32,
+    col: i32,
+    lossless: bool,
+    /// trivia, которые достанутся следующему токену
+    trivia: Vec<Trivia>,
+    finished: bool,
+    /// символы идентификаторов и строк, сравнимы только между токенами одного лексера
+    interner: Interner,
+}
+
+impl Lexer {
+    pub fn new(code: String) -> Self {
+        Lexer {
+            source: Rc::from(code),
+            offset: 0,
+            line: 1,
+            col: 0,
+            lossless: false,
+            trivia: Vec::new(),
+            finished: false,
+            interner: Interner::default(),
+        }
+    }
+
+    /// см. `tokenize_lossless`
+    pub fn lossless(code: String) -> Self {
+        Lexer { lossless: true, ..Lexer::new(code) }
+    }
+
+    /// продолжает lossless разбор source с состояния, снятого `checkpoint` на тексте с тем же префиксом,
+    /// interner - от предыдущего разбора, чтобы символы новых токенов совпадали со старыми
+    pub fn resume(source: Rc<str>, checkpoint: Checkpoint, interner: Interner) -> Self {
+        Lexer {
+            source,
+            offset: checkpoint.offset,
+            line: checkpoint.line,
+            col: checkpoint.col,
+            lossless: true,
+            trivia: Vec::new(),
+            finished: false,
+            interner,
+        }
+    }
+
+    /// отдает интернер следующему `resume`
+    pub fn into_interner(self) -> Interner {
+        self.interner
+    }
+
+    /// состояние после последнего отданного токена
+    pub fn checkpoint(&self) -> Checkpoint {
+        Checkpoint { offset: self.offset, line: self.line, col: self.col }
+    }
+
+    /// разбирает символ c, None - если он не дал ни токена, ни ошибки
+    fn scan(&mut self, c: char) -> Option<Result<Token, LexError>> {
+        self.col += 1;
+        let line = self.line;
+        let col = self.col;
+        // начало текущего символа в байтах
+        let start = self.offset - c.len_utf8();
+        let _type = match c {
+            '(' => TokenType::LEFT_PAREN,
+            ')' => TokenType::RIGHT_PAREN,
+            '{' => TokenType::LEFT_BRACE,
+            '}' => TokenType::RIGHT_BRACE,
+            ',' => TokenType::COMMA,
+            '.' => TokenType::DOT,
+            '+' => TokenType::PLUS,
+            '-' => TokenType::MINUS,
+            ';' => TokenType::SEMICOLON,
+            '*' => TokenType::STAR,
+            '=' => return Some(Ok(self.composite_token(start, TokenType::EQUAL_EQUAL, TokenType::EQUAL))),
+            '!' => return Some(Ok(self.composite_token(start, TokenType::BANG_EQUAL, TokenType::BANG))),
+            '<' => return Some(Ok(self.composite_token(start, TokenType::LESS_EQUAL, TokenType::LESS))),
+            '>' => return Some(Ok(self.composite_token(start, TokenType::GREATER_EQUAL, TokenType::GREATER))),
+            '/' => {
+                match self.peek() {
+                    Some('/') => {
+                        self.eat_while(|next| next != '\n');
+                        // `///` - документация, `////` и длиннее - обычный комментарий
+                        let comment = &self.source[start..self.offset];
+                        if comment.starts_with("///") && !comment.starts_with("////") {
+                            self.push_trivia(TriviaKind::DocComment, start);
+                        } else if self.lossless {
+                            self.push_trivia(TriviaKind::LineComment, start);
                         }
+                        return None;
                     }
-                    tokens.push(Token::new_char(TokenType::SLASH, c, line, col))
-                }
-                '"' => {
-                    let string_res = string(&mut data, line, col);
-                    if string_res.2 != 0 {
-                        result = string_res.2;
-                    } else {
-                        let cur_col = col;
-                        col = string_res.3;
-                        tokens.push(Token::new_content(TokenType::STRING, string_res.0, string_res.1, line, cur_col, col));
+                    Some('*') => {
+                        let closed = self.block_comment();
+                        if self.lossless {
+                            self.push_trivia(TriviaKind::BlockComment, start);
+                        }
+                        if !closed {
+                            return Some(Err(LexError::UnterminatedBlockComment { line, span: self.span(start) }));
+                        }
+                        return None;
                     }
+                    _ => TokenType::SLASH
                 }
-                '\n' => {
-                    line += 1;
-                    col = 1;
+            }
+            '"' => return Some(self.string(start)),
+            '\n' => {
+                self.line += 1;
+                self.col = 1;
+                if self.lossless {
+                    self.push_trivia(TriviaKind::Whitespace, start);
                 }
-                ' ' | '\r' | '\t' => continue,
-                _ => {
-                    // сперва строка, тк 6bz - 6 может распознаться как число, а bz отдельно identifier
-                    if c.is_ascii_alphabetic() || c == '_' {
-
-                        let identifier_res = identifier(c, &mut data, col);
-                        let cur_col = col;
-                        col = identifier_res.2;
-                        tokens.push(Token::new(identifier_res.0, identifier_res.1, line, cur_col, col));
-                    } else if c.is_numeric() {
-
-                        let num_result = number(c, &mut data, col);
-                        let cur_col = col;
-                        col = num_result.2;
-                        tokens.push(Token::new_content(TokenType::NUMBER, num_result.0, num_result.1, line, cur_col, col));
-                    } else {
-
-                        eprintln!("[line {}] Error: Unexpected character: {}", line, c);
-                        result = 65
-                    }
+                return None;
+            }
+            ' ' | '\r' | '\t' => {
+                if self.lossless {
+                    self.push_trivia(TriviaKind::Whitespace, start);
                 }
+                return None;
             }
-        }
+            // сперва строка, тк 6bz - 6 может распознаться как число, а bz отдельно identifier
+            _ if is_identifier_start(c) => return Some(Ok(self.identifier(start))),
+            _ if c.is_ascii_digit() => return Some(self.number(c, start)),
+            _ => {
+                if self.lossless {
+                    self.push_trivia(TriviaKind::Skipped, start);
+                }
+                return Some(Err(LexError::UnexpectedCharacter { character: c, line, span: self.span(start) }));
+            }
+        };
+
+        Some(Ok(self.token(_type, start, line, col, col)))
     }
 
-    tokens.push(Token::new(TokenType::EOF, String::new(), line, col, col));
+    /// токен от start до текущего оффсета, забирает накопленные trivia
+    fn token(&mut self, _type: TokenType, start: usize, line: i32, column_from: i32, column_to: i32) -> Token {
+        let mut token = Token::new(_type, &self.source, self.span(start), line, column_from, column_to);
+        token._trivia = std::mem::take(&mut self.trivia);
+        token
+    }
 
-    Tokens { tokens, code : result }
-}
+    /// диапазон от start до текущего оффсета
+    fn span(&self, start: usize) -> Span {
+        Span::new(start, self.offset)
+    }
 
-fn composite_token(data: &mut Peekable<Chars>,
-                   current_char: char,
-                   next_char: char,
-                   column: i32,
-                   then_token: TokenType,
-                   else_token: TokenType) -> (TokenType, String, i32) {
-    if let Some(&next) = data.peek() {
-        if next == next_char {
-            let mut result = String::from(current_char);
-            result.push(data.next().unwrap());
-            return (then_token, result, column + 1);
+    /// добавляет trivia от start до текущего оффсета,
+    /// идущие подряд пробелы и ошибочные символы склеиваются в одну
+    fn push_trivia(&mut self, kind: TriviaKind, start: usize) {
+        let span = Span::new(start, self.offset);
+        if let Some(last) = self.trivia.last_mut() {
+            let mergeable = matches!(kind, TriviaKind::Whitespace | TriviaKind::Skipped);
+            if mergeable && last.kind == kind && last.span.end == span.start {
+                last.span.end = span.end;
+                return;
+            }
         }
+        self.trivia.push(Trivia { kind, span, source: Rc::clone(&self.source) });
     }
-    (else_token, String::from(current_char), column + 1)
-}
 
-fn skip_while(data: &mut Peekable<Chars>, predict: impl Fn(char) -> bool) {
-    loop {
-        if let Some(&next) = data.peek() {
-            if !predict(next) {
-                break;
-            }
-            data.next();
+    /// следующий символ, не двигая оффсет
+    fn peek(&self) -> Option<char> {
+        self.source[self.offset..].chars().next()
+    }
+
+    /// символ после следующего, не двигая оффсет
+    fn peek_second(&self) -> Option<char> {
+        let mut chars = self.source[self.offset..].chars();
+        chars.next();
+        chars.next()
+    }
+
+    fn advance(&mut self) -> Option<char> {
+        let next = self.peek()?;
+        self.offset += next.len_utf8();
+        Some(next)
+    }
+
+    /// забирает символ, если он совпадает с ожидаемым
+    fn eat(&mut self, expected: char) -> bool {
+        if self.peek() == Some(expected) {
+            self.offset += expected.len_utf8();
+            true
         } else {
-            break;
+            false
         }
     }
-}
 
-fn string(data: &mut Peekable<Chars>, line: i32, col: i32) -> (String, String, u8, i32) {
-    let mut col = col;
-    let mut result: u8 = 0;
-    let mut value = String::new();
-    let mut string = String::from('"');
-
-    loop {
-        if let Some(next) = data.next() {
-            col += 1;
-            string.push(next);
-            if next == '"' {
-                break;
+    /// забирает символы, пока выполняется условие, и возвращает число забранных символов
+    fn eat_while(&mut self, predict: impl Fn(char) -> bool) -> usize {
+        let mut count = 0;
+        while self.peek().is_some_and(&predict) {
+            self.advance();
+            count += 1;
+        }
+        count
+    }
+
+    fn composite_token(&mut self, start: usize, then_token: TokenType, else_token: TokenType) -> Token {
+        let _type = if self.eat('=') { then_token } else { else_token };
+        self.token(_type, start, self.line, self.col, self.col + 1)
+    }
+
+    /// блочный комментарий `/* ... */` с вложенностью, первый `/` уже прочитан
+    /// возвращает, закрыт ли комментарий
+    fn block_comment(&mut self) -> bool {
+        let mut depth = 1;
+        self.col += 1;
+        self.advance().expect("block comment starts with /*");
+
+        while let Some(next) = self.advance() {
+            self.col += 1;
+            match (next, self.peek()) {
+                ('/', Some('*')) | ('*', Some('/')) => {
+                    depth += if next == '/' { 1 } else { -1 };
+                    self.col += 1;
+                    self.advance();
+                    if depth == 0 {
+                        return true;
+                    }
+                }
+                ('\n', _) => {
+                    self.line += 1;
+                    self.col = 1;
+                }
+                _ => {}
             }
-            value.push(next);
-        } else {
-            eprintln!("[line {}] Error: Unterminated string.", line);
-            result = 65;
-            break;
         }
+
+        false
     }
 
-    (string, value, result, col)
-}
+    /// строка до закрывающей кавычки, открывающая уже прочитана
+    fn string(&mut self, start: usize) -> Result<Token, LexError> {
+        let mut col = self.col;
 
-fn number(current: char, data: &mut Peekable<Chars>, col: i32) -> (String, String, i32) {
-    let mut col = col;
-    let mut dot_index: i32 = -1;
+        loop {
+            match self.advance() {
+                Some(next) => {
+                    col += 1;
+                    if next == '"' {
+                        break;
+                    }
+                }
+                None => {
+                    if self.lossless {
+                        self.push_trivia(TriviaKind::Skipped, start);
+                    }
+                    return Err(LexError::UnterminatedString { line: self.line, span: self.span(start) });
+                }
+            }
+        }
 
-    let mut value = String::from(current);
-    let mut number = String::from(current);
+        let cur_col = self.col;
+        self.col = col;
+        let mut token = self.token(TokenType::STRING, start, self.line, cur_col, col);
+        token._symbol = Some(self.interner.intern(&self.source[start + 1..self.offset - 1]));
+        Ok(token)
+    }
 
-    loop {
-        if let Some(&next) = data.peek() {
-            if !next.is_numeric() && next != '.' {
-                break;
+    /// число: `123`, `1_000`, `12.5`, `1e-9`, `0x1F`, `0b1010`, первая цифра уже прочитана
+    /// значение считает `number_value`
+    fn number(&mut self, current: char, start: usize) -> Result<Token, LexError> {
+        let checked = match (current, self.peek()) {
+            ('0', Some('x' | 'X' | 'b' | 'B')) => {
+                let radix = if matches!(self.advance(), Some('x' | 'X')) { 16 } else { 2 };
+                // забираем весь хвост, чтобы 0b102 и 0xZZ были одной ошибкой, а не числом + identifier
+                self.eat_while(|next| next.is_ascii_alphanumeric() || next == '_');
+                let digits = &self.source[start + 2..self.offset];
+                check_digits(digits, radix).and_then(|_| check_finite(radix_value(digits, radix)))
             }
+            _ => {
+                self.eat_while(|next| next.is_ascii_digit() || next == '_');
 
-            col += 1;
-            value.push(next);
-            number.push(next);
+                // точка входит в число, только если за ней цифра: `1.` - это NUMBER и DOT
+                if self.peek() == Some('.') && self.peek_second().is_some_and(|next| next.is_ascii_digit()) {
+                    self.advance();
+                    self.eat_while(|next| next.is_ascii_digit() || next == '_');
+                }
+
+                // экспонента, только если за `e` идет цифра или знак с цифрой, иначе `2else` - NUMBER и identifier
+                if let Some('e' | 'E') = self.peek() {
+                    let mark = self.offset;
+                    self.advance();
+                    if matches!(self.peek(), Some('+' | '-')) {
+                        self.advance();
+                    }
+                    if self.peek().is_some_and(|next| next.is_ascii_digit()) {
+                        self.eat_while(|next| next.is_ascii_digit() || next == '_');
+                    } else {
+                        self.offset = mark;
+                    }
+                }
 
-            if next == '.' {
-                dot_index = value.len() as i32;
+                check_decimal(&self.source[start..self.offset])
             }
+        };
 
-            data.next();
-        } else {
-            break;
+        let line = self.line;
+        let cur_col = self.col;
+        let number = &self.source[start..self.offset];
+        let col = cur_col + number.chars().count() as i32 - 1;
+        self.col = col;
+        match checked {
+            Ok(()) => Ok(self.token(TokenType::NUMBER, start, line, cur_col, col)),
+            Err(reason) => {
+                let literal = number.to_string();
+                if self.lossless {
+                    self.push_trivia(TriviaKind::Skipped, start);
+                }
+                Err(LexError::InvalidNumber { literal, reason, line, span: self.span(start) })
+            }
         }
     }
 
-    if dot_index == -1 {
-        value.push_str(".0");
-    } else {
-        let mut len = value.len();
-        for (i, val) in value.char_indices().rev() {
-            // убираем все 0 после точки, оставляя только первую
-            if val != '0' || dot_index == i as i32 {
-                break;
-            }
-            len -= 1;
+    /// идентификатор или ключевое слово, первый символ уже прочитан
+    fn identifier(&mut self, start: usize) -> Token {
+        let cur_col = self.col;
+        self.col += self.eat_while(is_identifier_continue) as i32;
+
+        let _type = match KEYWORDS.get(&self.source[start..self.offset]) {
+            None => TokenType::IDENTIFIER,
+            Some(keyword) => TokenType::KEYWORD(keyword.clone())
+        };
+        let mut token = self.token(_type, start, self.line, cur_col, self.col);
+        if token._type == TokenType::IDENTIFIER {
+            token._symbol = Some(self.interner.intern(token.lexeme()));
         }
-        value.truncate(len);
+        token
     }
+}
+
+impl Iterator for Lexer {
+    type Item = Result<Token, LexError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished {
+            return None;
+        }
+        while let Some(c) = self.advance() {
+            if let Some(item) = self.scan(c) {
+                return Some(item);
+            }
+        }
 
-    (number, value, col)
+        // хвостовые trivia достаются EOF
+        self.finished = true;
+        Some(Ok(self.token(TokenType::EOF, self.offset, self.line, self.col, self.col)))
+    }
 }
 
-fn identifier(current: char, data: &mut Peekable<Chars>, col: i32) -> (TokenType, String, i32) {
-    let mut col = col;
-    let mut result = String::from(current);
-
-    loop {
-        if let Some(&next) = data.peek() {
-            if next.is_ascii_alphanumeric() || next == '_' || next.is_numeric() {
-                col += 1;
-                result.push(next);
-                data.next();
-            } else {
-                break;
+/// значение числового литерала, который уже проверил лексер, в каноничном виде `N.0`
+pub fn number_value(number: &str) -> String {
+    match number.get(..2) {
+        Some("0x" | "0X") => canonical_number(radix_value(&number[2..], 16)),
+        Some("0b" | "0B") => canonical_number(radix_value(&number[2..], 2)),
+        _ if number.contains(['e', 'E']) => canonical_number(decimal_float(number)),
+        _ => {
+            let mut value = number.replace('_', "");
+            match value.find('.') {
+                None => value.push_str(".0"),
+                Some(dot_index) => {
+                    // убираем все 0 после точки, оставляя только первую
+                    let trimmed = value.trim_end_matches('0').len().max(dot_index + 2);
+                    value.truncate(trimmed);
+                }
             }
-        } else {
-            break;
+            value
         }
     }
+}
 
-    match KEYWORDS.get(&result.as_str()) {
-        None => (TokenType::IDENTIFIER, result, col),
-        Some(keyword) => (TokenType::KEYWORD(keyword.clone()), result, col)
+/// проверка десятичного литерала, с экспонентой - еще и на переполнение
+fn check_decimal(number: &str) -> Result<(), String> {
+    for part in number.split(['.', 'e', 'E']) {
+        check_digits(part.trim_start_matches(['+', '-']), 10)?;
     }
+    if number.contains(['e', 'E']) {
+        check_finite(decimal_float(number))?;
+    }
+    Ok(())
+}
+
+fn check_finite(value: f64) -> Result<(), String> {
+    if value.is_finite() { Ok(()) } else { Err("number is too large".to_string()) }
+}
+
+fn decimal_float(number: &str) -> f64 {
+    let digits = if number.contains('_') { Cow::Owned(number.replace('_', "")) } else { Cow::Borrowed(number) };
+    digits.parse::<f64>().unwrap_or(f64::INFINITY)
+}
+
+/// значение 0x / 0b литерала, digits - без префикса
+fn radix_value(digits: &str, radix: u32) -> f64 {
+    digits.chars()
+        .filter_map(|digit| digit.to_digit(radix))
+        .fold(0f64, |acc, digit| acc * radix as f64 + digit as f64)
+}
+
+/// группа цифр: не пустая, `_` только между цифрами
+fn check_digits(digits: &str, radix: u32) -> Result<(), String> {
+    let kind = match radix {
+        16 => "hexadecimal",
+        2 => "binary",
+        _ => "decimal",
+    };
+
+    if digits.is_empty() {
+        return Err(format!("missing {} digits", kind));
+    }
+    if digits.starts_with('_') || digits.ends_with('_') || digits.contains("__") {
+        return Err("'_' must separate digits".to_string());
+    }
+    match digits.chars().find(|digit| *digit != '_' && !digit.is_digit(radix)) {
+        Some(digit) => Err(format!("invalid {} digit '{}'", kind, digit)),
+        None => Ok(()),
+    }
+}
+
+/// число в том виде, в каком его печатает `tokenize`: `7.0`, `0.5`, `-3.0`
+pub fn canonical_number(value: f64) -> String {
+    let value = value.to_string();
+    if value.contains('.') { value } else { value + ".0" }
+}
+
+/// идентификатор начинается с XID_Start или `_`
+fn is_identifier_start(c: char) -> bool {
+    c == '_' || UnicodeXID::is_xid_start(c)
+}
+
+/// продолжение идентификатора - XID_Continue (включает `_` и цифры)
+fn is_identifier_continue(c: char) -> bool {
+    UnicodeXID::is_xid_continue(c)
 }
 
 lazy_static! {
@@ -0,0 +1,195 @@
+use crate::domain::{Expr, Token, TokenType, Tokens, TriviaKind, AST};
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::path::Path;
+use std::fs;
+
+pub const CONFIG_FILE: &str = "lox.toml";
+
+/// правило линтера: id используется в `lox.toml` и в `// lox-allow(id)`
+pub struct Rule {
+    pub id: &'static str,
+    pub default: Severity,
+}
+
+pub const RULES: &[Rule] = &[
+    // `x == x`, `(a + 1) < (a + 1)`: результат известен заранее, обычно это опечатка
+    Rule {
+        id: "self-comparison",
+        default: Severity::Warn,
+    },
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Allow,
+    Warn,
+    Deny,
+}
+
+impl Severity {
+    fn parse(severity: &str) -> Option<Severity> {
+        match severity {
+            "allow" => Some(Severity::Allow),
+            "warn" => Some(Severity::Warn),
+            "deny" => Some(Severity::Deny),
+            _ => None
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub rule: &'static str,
+    pub severity: Severity,
+    pub message: String,
+    pub token: Token,
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let level = if self.severity == Severity::Deny { "Error" } else { "Warning" };
+        write!(f, "[line {}] {}: {} [{}]", self.token._line, level, self.message, self.rule)
+    }
+}
+
+/// уровни правил, по умолчанию - `Rule::default`
+#[derive(Debug, Clone, Default)]
+pub struct LintConfig {
+    severities: HashMap<String, Severity>,
+}
+
+impl LintConfig {
+    /// секция `[lint]` из `lox.toml`: `rule-id = "allow" | "warn" | "deny"`
+    pub fn parse(config: &str) -> Result<LintConfig, String> {
+        let table = config.parse::<toml::Table>().map_err(|ex| ex.to_string())?;
+        let mut severities = HashMap::new();
+
+        if let Some(lint) = table.get("lint") {
+            let lint = lint.as_table().ok_or("[lint] must be a table")?;
+            for (id, severity) in lint {
+                if !RULES.iter().any(|rule| rule.id == id) {
+                    return Err(format!("unknown lint rule '{}'", id));
+                }
+                let severity = severity.as_str()
+                    .and_then(Severity::parse)
+                    .ok_or(format!("severity of '{}' must be \"allow\", \"warn\" or \"deny\"", id))?;
+                severities.insert(id.clone(), severity);
+            }
+        }
+
+        Ok(LintConfig { severities })
+    }
+
+    /// ищет `lox.toml` в папке файла и выше, без конфига - уровни по умолчанию
+    pub fn find(file: &Path) -> Result<LintConfig, String> {
+        let mut dir = file.parent();
+        while let Some(current) = dir {
+            let candidate = current.join(CONFIG_FILE);
+            if candidate.is_file() {
+                let config = fs::read_to_string(&candidate).map_err(|ex| ex.to_string())?;
+                return LintConfig::parse(&config).map_err(|ex| format!("{}: {}", candidate.display(), ex));
+            }
+            dir = current.parent();
+        }
+        Ok(LintConfig::default())
+    }
+
+    fn severity(&self, rule: &Rule) -> Severity {
+        *self.severities.get(rule.id).unwrap_or(&rule.default)
+    }
+}
+
+/// проверяет AST, tokens нужны для комментариев `// lox-allow(rule)`:
+/// такой комментарий выключает правило на своей строке и на следующей
+pub fn lint(ast: &AST, tokens: &Tokens, config: &LintConfig) -> Vec<Diagnostic> {
+    let allowed = allowed_rules(tokens);
+    let mut diagnostics = Vec::new();
+    check_expr(&ast.expr, &mut diagnostics);
+
+    diagnostics.into_iter()
+        .filter_map(|mut diagnostic| {
+            let rule = RULES.iter().find(|rule| rule.id == diagnostic.rule)?;
+            let line = diagnostic.token._line;
+            let allowed = allowed.iter()
+                .any(|(id, allowed_line)| id == rule.id && (line == *allowed_line || line == allowed_line + 1));
+            diagnostic.severity = config.severity(rule);
+            (!allowed && diagnostic.severity != Severity::Allow).then_some(diagnostic)
+        })
+        .collect()
+}
+
+fn check_expr(expr: &Expr, diagnostics: &mut Vec<Diagnostic>) {
+    match expr {
+        Expr::Binary(left, operator, right) => {
+            if is_comparison(&operator._type) && same_expr(left, right) {
+                diagnostics.push(Diagnostic {
+                    rule: "self-comparison",
+                    severity: Severity::Warn,
+                    message: format!("both sides of '{}' are the same expression", operator.lexeme()),
+                    token: operator.clone(),
+                });
+            }
+            check_expr(left, diagnostics);
+            check_expr(right, diagnostics);
+        }
+        Expr::Unary(_, right) => check_expr(right, diagnostics),
+        Expr::Grouping(inner) => check_expr(inner, diagnostics),
+        Expr::Literal(_, _) => {}
+    }
+}
+
+fn is_comparison(token_type: &TokenType) -> bool {
+    matches!(token_type, TokenType::EQUAL_EQUAL | TokenType::BANG_EQUAL
+        | TokenType::GREATER | TokenType::GREATER_EQUAL | TokenType::LESS | TokenType::LESS_EQUAL)
+}
+
+/// выражения совпадают по структуре и лексемам
+fn same_expr(left: &Expr, right: &Expr) -> bool {
+    match (left, right) {
+        (Expr::Literal(_, left), Expr::Literal(_, right)) => {
+            match (left._symbol, right._symbol) {
+                (Some(left_symbol), Some(right_symbol)) => left._type == right._type && left_symbol == right_symbol,
+                _ => left._type == right._type && left.lexeme() == right.lexeme()
+            }
+        }
+        (Expr::Unary(left_op, left), Expr::Unary(right_op, right)) => {
+            left_op._type == right_op._type && same_expr(left, right)
+        }
+        (Expr::Binary(left_l, left_op, left_r), Expr::Binary(right_l, right_op, right_r)) => {
+            left_op._type == right_op._type && same_expr(left_l, right_l) && same_expr(left_r, right_r)
+        }
+        (Expr::Grouping(left), Expr::Grouping(right)) => same_expr(left, right),
+        _ => false
+    }
+}
+
+/// пары (правило, строка комментария) из `// lox-allow(rule, other-rule)`
+fn allowed_rules(tokens: &Tokens) -> Vec<(String, i32)> {
+    let mut allowed = Vec::new();
+    for token in &tokens.tokens {
+        for trivia in &token._trivia {
+            if trivia.kind != TriviaKind::LineComment {
+                continue;
+            }
+            let text = trivia.text().trim_start_matches('/').trim();
+            let Some((rules, _)) = text.strip_prefix("lox-allow(").and_then(|rules| rules.split_once(')')) else {
+                continue;
+            };
+            // комментарий стоит перед токеном: его строка - строка токена минус переводы строк после него
+            let line = token._line - newlines_after(token, trivia.span.end);
+            for rule in rules.split(',') {
+                allowed.push((rule.trim().to_string(), line));
+            }
+        }
+    }
+    allowed
+}
+
+/// сколько переводов строк в trivia токена после позиции
+fn newlines_after(token: &Token, offset: usize) -> i32 {
+    token._trivia.iter()
+        .filter(|trivia| trivia.span.start >= offset)
+        .map(|trivia| trivia.text().matches('\n').count() as i32)
+        .sum()
+}
@@ -0,0 +1,305 @@
+use crate::cst::check_complete;
+use crate::domain::{ParserError, Span, Token, TokenType};
+use crate::incremental::{Document, TextEdit};
+use crate::lexer::tokenize_lossless;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+use unicode_xid::UnicodeXID;
+
+/// сервер Language Server Protocol: JSON-RPC с заголовком Content-Length поверх input/output
+/// документы синхронизируются инкрементально (правками по range), на каждое изменение публикуются диагностики
+pub fn serve(mut input: impl BufRead, mut output: impl Write) -> io::Result<()> {
+    let mut server = Server { documents: HashMap::new() };
+
+    while let Some(message) = read_message(&mut input)? {
+        // id запроса из битого сообщения неизвестен, поэтому в ответе id - null
+        let message = match message {
+            Ok(message) => message,
+            Err(error) => {
+                let response = json!({ "jsonrpc": "2.0", "id": null, "error": { "code": PARSE_ERROR, "message": error } });
+                write_message(&mut output, &response)?;
+                continue;
+            }
+        };
+        let method = message["method"].as_str().unwrap_or_default();
+        if method == "exit" {
+            break;
+        }
+
+        let (response, notifications) = server.handle(method, &message["params"]);
+        for notification in notifications {
+            write_message(&mut output, &notification)?;
+        }
+        // у уведомлений нет id, ответ на них не нужен
+        if let Some(id) = message.get("id") {
+            let response = match response {
+                Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+                Err((code, error)) => json!({ "jsonrpc": "2.0", "id": id, "error": { "code": code, "message": error } }),
+            };
+            write_message(&mut output, &response)?;
+        }
+    }
+
+    Ok(())
+}
+
+const PARSE_ERROR: i32 = -32700;
+const METHOD_NOT_FOUND: i32 = -32601;
+const INVALID_PARAMS: i32 = -32602;
+
+struct Server {
+    documents: HashMap<String, Document>,
+}
+
+impl Server {
+    /// результат запроса и уведомления, которые надо отправить клиенту
+    fn handle(&mut self, method: &str, params: &Value) -> (Result<Value, (i32, String)>, Vec<Value>) {
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or_default().to_string();
+
+        match method {
+            "initialize" => (Ok(json!({
+                "capabilities": {
+                    "textDocumentSync": 2,
+                    "hoverProvider": true,
+                    "referencesProvider": true,
+                    "renameProvider": true,
+                },
+                "serverInfo": { "name": "lox-lsp" },
+            })), Vec::new()),
+            "initialized" | "$/cancelRequest" | "$/setTrace" => (Ok(Value::Null), Vec::new()),
+            "shutdown" => (Ok(Value::Null), Vec::new()),
+            "textDocument/didOpen" => {
+                let text = params["textDocument"]["text"].as_str().unwrap_or_default();
+                self.documents.insert(uri.clone(), Document::new(text.to_string()));
+                (Ok(Value::Null), vec![self.diagnostics(&uri)])
+            }
+            "textDocument/didChange" => {
+                // инкрементальная синхронизация: изменения с range применяются по порядку,
+                // изменение без range содержит весь текст
+                let changes = params["contentChanges"].as_array().cloned().unwrap_or_default();
+                let document = self.documents.entry(uri.clone()).or_insert_with(|| Document::new(String::new()));
+                for change in changes {
+                    let text = change["text"].as_str().unwrap_or_default().to_string();
+                    let range = &change["range"];
+                    if range.is_null() {
+                        *document = Document::new(text);
+                        continue;
+                    }
+                    // битый range пропускаем: лучше потерять одну правку, чем весь текст
+                    if let (Some(start), Some(end)) = (edit_offset(document.text(), &range["start"]),
+                                                       edit_offset(document.text(), &range["end"])) {
+                        if start <= end {
+                            document.edit(&TextEdit { span: Span::new(start, end), text });
+                        }
+                    }
+                }
+                (Ok(Value::Null), vec![self.diagnostics(&uri)])
+            }
+            "textDocument/didClose" => {
+                self.documents.remove(&uri);
+                (Ok(Value::Null), vec![publish(&uri, Vec::new())])
+            }
+            "textDocument/hover" => (Ok(self.hover(&uri, &params["position"])), Vec::new()),
+            "textDocument/references" => (Ok(self.references(&uri, &params["position"])), Vec::new()),
+            "textDocument/rename" => {
+                let new_name = params["newName"].as_str().unwrap_or_default();
+                (self.rename(&uri, &params["position"], new_name), Vec::new())
+            }
+            _ => (Err((METHOD_NOT_FOUND, format!("Unknown method {}", method))), Vec::new()),
+        }
+    }
+
+    fn document(&self, uri: &str) -> (&str, &[Token]) {
+        match self.documents.get(uri) {
+            Some(document) => (document.text(), document.tokens()),
+            None => ("", &[]),
+        }
+    }
+
+    /// все ошибки лексера и первая ошибка парсера
+    fn diagnostics(&self, uri: &str) -> Value {
+        let Some(document) = self.documents.get(uri) else {
+            return publish(uri, Vec::new());
+        };
+        let text = document.text();
+        let mut diagnostics: Vec<Value> = document.errors().iter()
+            .map(|error| diagnostic(text, error.span(), &error.to_string()))
+            .collect();
+
+        // дерево документа перестраивается только вокруг правки, поэтому
+        // синтаксис проверяем по нему, а не полным разбором на каждое изменение
+        if diagnostics.is_empty() {
+            if let Err(ParserError::Default(message, token, _)) = check_complete(document.tree()) {
+                diagnostics.push(diagnostic(text, token._span, &message));
+            }
+        }
+
+        publish(uri, diagnostics)
+    }
+
+    fn hover(&self, uri: &str, position: &Value) -> Value {
+        let (text, tokens) = self.document(uri);
+        let Some(token) = token_at(text, tokens, position) else {
+            return Value::Null;
+        };
+
+        let mut contents = match &token._type {
+            TokenType::IDENTIFIER => format!("global `{}`", token.lexeme()),
+            TokenType::NUMBER => format!("number `{}`", token.value().unwrap_or_default()),
+            TokenType::STRING => format!("string of {} characters", token.value().unwrap_or_default().chars().count()),
+            TokenType::KEYWORD(keyword) => format!("keyword `{}`", keyword.to_string().to_lowercase()),
+            _ => return Value::Null,
+        };
+        if let Some(doc) = token.doc_comment() {
+            contents.push_str("\n\n");
+            contents.push_str(&doc);
+        }
+
+        json!({
+            "contents": { "kind": "markdown", "value": contents },
+            "range": range(text, token._span),
+        })
+    }
+
+    /// все вхождения идентификатора: объявлений в языке нет, все имена - глобальные
+    fn references(&self, uri: &str, position: &Value) -> Value {
+        let (text, tokens) = self.document(uri);
+        let spans = occurrences(text, tokens, position);
+        Value::Array(spans.into_iter()
+            .map(|span| json!({ "uri": uri, "range": range(text, span) }))
+            .collect())
+    }
+
+    fn rename(&self, uri: &str, position: &Value, new_name: &str) -> Result<Value, (i32, String)> {
+        let mut chars = new_name.chars();
+        let valid = chars.next().is_some_and(|c| c == '_' || c.is_xid_start())
+            && chars.all(|c| c.is_xid_continue())
+            && tokenize_lossless(new_name.to_string()).tokens[0]._type == TokenType::IDENTIFIER;
+        if !valid {
+            return Err((INVALID_PARAMS, format!("'{}' is not a valid identifier", new_name)));
+        }
+
+        let (text, tokens) = self.document(uri);
+        let edits: Vec<Value> = occurrences(text, tokens, position).into_iter()
+            .map(|span| json!({ "range": range(text, span), "newText": new_name }))
+            .collect();
+        if edits.is_empty() {
+            return Ok(Value::Null);
+        }
+        Ok(json!({ "changes": { uri: edits } }))
+    }
+}
+
+fn diagnostic(text: &str, span: Span, message: &str) -> Value {
+    json!({
+        "range": range(text, span),
+        "severity": 1,
+        "source": "lox",
+        "message": message,
+    })
+}
+
+fn publish(uri: &str, diagnostics: Vec<Value>) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "method": "textDocument/publishDiagnostics",
+        "params": { "uri": uri, "diagnostics": diagnostics },
+    })
+}
+
+/// токен под курсором, курсор сразу после токена тоже считается
+fn token_at<'a>(text: &str, tokens: &'a [Token], position: &Value) -> Option<&'a Token> {
+    let offset = offset(text, position)?;
+    tokens.iter()
+        .filter(|token| token._type != TokenType::EOF)
+        .find(|token| token._span.start <= offset && offset <= token._span.end)
+}
+
+/// спаны всех идентификаторов с тем же именем, что под курсором
+fn occurrences(text: &str, tokens: &[Token], position: &Value) -> Vec<Span> {
+    let Some(name) = token_at(text, tokens, position)
+        .filter(|token| token._type == TokenType::IDENTIFIER)
+        .and_then(|token| token._symbol) else {
+        return Vec::new();
+    };
+
+    tokens.iter()
+        .filter(|token| token._type == TokenType::IDENTIFIER && token._symbol == Some(name))
+        .map(|token| token._span)
+        .collect()
+}
+
+/// LSP позиция - строка с 0 и колонка в UTF-16 единицах
+fn position(text: &str, offset: usize) -> Value {
+    let before = &text[..offset];
+    let line_start = before.rfind('\n').map(|index| index + 1).unwrap_or(0);
+    json!({
+        "line": before.matches('\n').count(),
+        "character": before[line_start..].encode_utf16().count(),
+    })
+}
+
+fn range(text: &str, span: Span) -> Value {
+    json!({ "start": position(text, span.start), "end": position(text, span.end) })
+}
+
+/// байтовый оффсет LSP позиции, None - если позиция за пределами текста
+fn offset(text: &str, position: &Value) -> Option<usize> {
+    let line = position["line"].as_u64()? as usize;
+    let character = position["character"].as_u64()? as usize;
+
+    let line_start = if line == 0 {
+        0
+    } else {
+        text.match_indices('\n').nth(line - 1)?.0 + 1
+    };
+    let line_text = text[line_start..].split('\n').next().unwrap_or_default();
+
+    let mut units = 0;
+    for (index, c) in line_text.char_indices() {
+        if units >= character {
+            return Some(line_start + index);
+        }
+        units += c.len_utf16();
+    }
+    Some(line_start + line_text.len())
+}
+
+/// позиция правки: строки за концом текста прижимаются к его концу, None - позиция не из чисел
+fn edit_offset(text: &str, position: &Value) -> Option<usize> {
+    position["line"].as_u64()?;
+    position["character"].as_u64()?;
+    Some(offset(text, position).unwrap_or(text.len()))
+}
+
+/// читает одно сообщение, None - конец ввода, Err внутри - сообщение без Content-Length или не JSON
+fn read_message(input: &mut impl BufRead) -> io::Result<Option<Result<Value, String>>> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if input.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some(value) = header.strip_prefix("Content-Length:") {
+            length = value.trim().parse::<usize>().ok();
+        }
+    }
+
+    let Some(length) = length else {
+        return Ok(Some(Err("Missing Content-Length header".to_string())));
+    };
+    let mut body = vec![0; length];
+    input.read_exact(&mut body)?;
+    Ok(Some(serde_json::from_slice(&body).map_err(|ex| format!("Invalid JSON: {}", ex))))
+}
+
+fn write_message(output: &mut impl Write, message: &Value) -> io::Result<()> {
+    let body = message.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    output.flush()
+}
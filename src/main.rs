@@ -1,37 +1,86 @@
 mod lexer;
 mod parser;
 mod domain;
+mod interner;
+mod cst;
+mod incremental;
+mod formatter;
+mod linter;
+mod checker;
+mod optimizer;
+mod lsp;
+mod highlight;
 mod test;
 
-use std::env;
 use std::io::{self, Write};
+use std::path::Path;
+use std::{env, fs};
 use std::process::ExitCode;
-use crate::domain::ParserError;
+use crate::domain::{LexError, ParserError};
 
 fn main() -> ExitCode {
     let args: Vec<String> = env::args().collect();
+    if args.get(1).is_some_and(|command| command == "lsp") {
+        return match lsp::serve(io::stdin().lock(), io::stdout().lock()) {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(ex) => {
+                eprintln!("lsp: {}", ex);
+                ExitCode::FAILURE
+            }
+        };
+    }
     if args.len() < 3 {
         writeln!(io::stderr(), "Usage: {} tokenize <filename>", args[0]).unwrap();
         return ExitCode::SUCCESS;
     }
 
     let command = &args[1];
-    let filename = &args[2];
+    // флаги могут стоять и до имени файла: `fmt --check f.lox`
+    let options = &args[2..];
+    let Some(filename) = positional(options) else {
+        eprintln!("Usage: {} {} <filename> [options]", args[0], command);
+        return ExitCode::from(64);
+    };
 
     match command.as_str() {
         "tokenize" => {
-            let result = lexer::tokenize(filename);
-            for x in result.tokens {
-                println!("{}", x);
+            let mut code = 0;
+            for token in lexer::Lexer::new(read_source(filename)) {
+                match token {
+                    Ok(token) => println!("{}", token),
+                    Err(error) => {
+                        report_lex(&error);
+                        code = 65;
+                    }
+                }
             }
-            return ExitCode::from(result.code);
+            return ExitCode::from(code);
         }
         "parse" => {
-            let result = lexer::tokenize(filename);
+            let dump_optimized = options.iter().any(|option| option == "--dump-optimized");
+            // вычислителя пока нет, уровень влияет только на печать оптимизированного дерева
+            if option_value(options, "--opt-level").is_some() && !dump_optimized {
+                eprintln!("--opt-level only applies together with --dump-optimized");
+                return ExitCode::from(64);
+            }
+            let level = match option_value(options, "--opt-level").map(|level| level.parse::<u8>()) {
+                None => optimizer::MAX_OPT_LEVEL,
+                Some(Ok(level)) if level <= optimizer::MAX_OPT_LEVEL => level,
+                Some(_) => {
+                    eprintln!("Invalid --opt-level, expected a number from 0 to {}", optimizer::MAX_OPT_LEVEL);
+                    return ExitCode::from(64);
+                }
+            };
+
+            let result = lexer::tokenize_code(read_source(filename));
+            result.errors.iter().for_each(report_lex);
             if result.code != 0 {
                 return ExitCode::from(result.code);
             }
             match parser::parse(result) {
+                Ok(ast) if dump_optimized => {
+                    println!("{}", optimizer::optimize(ast.expr, level));
+                }
                 Ok(ast) => {
                     println!("{}", ast.expr);
                 }
@@ -42,6 +91,120 @@ fn main() -> ExitCode {
                 }
             }
         }
+        "fmt" => {
+            let check = options.iter().any(|option| option == "--check");
+            let width = match option_value(options, "--width").map(|width| width.parse::<usize>()) {
+                None => formatter::DEFAULT_WIDTH,
+                Some(Ok(width)) => width,
+                Some(Err(_)) => {
+                    eprintln!("Invalid --width, expected a number");
+                    return ExitCode::from(64);
+                }
+            };
+
+            let code = match fs::read_to_string(filename) {
+                Ok(code) => code,
+                Err(ex) => {
+                    eprintln!("Failed to read file {}, ex: {}", filename, ex);
+                    return ExitCode::from(66);
+                }
+            };
+            let tokens = lexer::tokenize_lossless(code.clone());
+            tokens.errors.iter().for_each(report_lex);
+            if tokens.code != 0 {
+                return ExitCode::from(tokens.code);
+            }
+
+            match formatter::format(tokens, width) {
+                Ok(formatted) if check => {
+                    if formatted != code {
+                        eprintln!("{} is not formatted", filename);
+                        return ExitCode::FAILURE;
+                    }
+                }
+                Ok(formatted) => {
+                    if formatted != code {
+                        if let Err(ex) = fs::write(filename, formatted) {
+                            eprintln!("Failed to write file {}, ex: {}", filename, ex);
+                            return ExitCode::from(74);
+                        }
+                    }
+                }
+                Err(e) => return report(e),
+            }
+        }
+        "lint" => {
+            let config = match linter::LintConfig::find(Path::new(filename)) {
+                Ok(config) => config,
+                Err(ex) => {
+                    eprintln!("Invalid {}: {}", linter::CONFIG_FILE, ex);
+                    return ExitCode::from(78);
+                }
+            };
+            let code = match fs::read_to_string(filename) {
+                Ok(code) => code,
+                Err(ex) => {
+                    eprintln!("Failed to read file {}, ex: {}", filename, ex);
+                    return ExitCode::from(66);
+                }
+            };
+            let tokens = lexer::tokenize_lossless(code);
+            tokens.errors.iter().for_each(report_lex);
+            if tokens.code != 0 {
+                return ExitCode::from(tokens.code);
+            }
+
+            match parser::parse(tokens.clone()) {
+                Ok(ast) => {
+                    let diagnostics = linter::lint(&ast, &tokens, &config);
+                    for diagnostic in &diagnostics {
+                        println!("{}", diagnostic);
+                    }
+                    if diagnostics.iter().any(|diagnostic| diagnostic.severity == linter::Severity::Deny) {
+                        return ExitCode::FAILURE;
+                    }
+                }
+                Err(e) => return report(e),
+            }
+        }
+        "check" => {
+            let tokens = lexer::tokenize_code(read_source(filename));
+            tokens.errors.iter().for_each(report_lex);
+            if tokens.code != 0 {
+                return ExitCode::from(tokens.code);
+            }
+
+            match parser::parse(tokens) {
+                Ok(ast) => {
+                    let (_, errors) = checker::check(&ast.expr);
+                    for error in &errors {
+                        eprintln!("{}", error);
+                    }
+                    if !errors.is_empty() {
+                        return ExitCode::from(65);
+                    }
+                }
+                Err(e) => return report(e),
+            }
+        }
+        "highlight" => {
+            let code = match fs::read_to_string(filename) {
+                Ok(code) => code,
+                Err(ex) => {
+                    eprintln!("Failed to read file {}, ex: {}", filename, ex);
+                    return ExitCode::from(66);
+                }
+            };
+            let tokens = lexer::tokenize_lossless(code.clone());
+            tokens.errors.iter().for_each(report_lex);
+            let highlights = highlight::classify(&tokens);
+            if options.iter().any(|option| option == "--html") {
+                print!("{}", highlight::to_html(&code, &highlights, filename));
+            } else {
+                println!("{}", highlight::to_json(&highlights));
+            }
+            return ExitCode::from(tokens.code);
+        }
         _ => {
             writeln!(io::stderr(), "Unknown command: {}", command).unwrap();
         }
@@ -49,3 +212,56 @@ fn main() -> ExitCode {
 
     ExitCode::SUCCESS
 }
+
+/// печатает ошибку разбора и возвращает ее код
+fn report(error: ParserError) -> ExitCode {
+    match error {
+        ParserError::Default(message, token, code) => {
+            eprintln!("[line {}] Error at '{}': {}", token._line, token.lexeme(), message);
+            ExitCode::from(code)
+        }
+        ParserError::Lex(error) => {
+            report_lex(&error);
+            ExitCode::from(65)
+        }
+    }
+}
+
+fn report_lex(error: &LexError) {
+    eprintln!("[line {}] Error: {}", error.line(), error);
+}
+
+/// текст файла, если файл не прочитался - пустая строка
+fn read_source(filename: &String) -> String {
+    fs::read_to_string(filename).unwrap_or_else(|ex| {
+        writeln!(io::stderr(), "Failed to read file {}, ex: {}", *filename, ex).unwrap();
+        String::new()
+    })
+}
+
+/// значение опции `--name value` или `--name=value`
+/// флаги, за которыми идет значение: `--width 80`
+const VALUE_OPTIONS: [&str; 2] = ["--width", "--opt-level"];
+
+/// первый аргумент, который не флаг и не значение флага
+fn positional(options: &[String]) -> Option<&String> {
+    let mut options = options.iter();
+    while let Some(option) = options.next() {
+        if VALUE_OPTIONS.contains(&option.as_str()) {
+            options.next();
+        } else if !option.starts_with("--") {
+            return Some(option);
+        }
+    }
+    None
+}
+
+fn option_value<'a>(options: &'a [String], name: &str) -> Option<&'a str> {
+    options.iter().enumerate().find_map(|(index, option)| {
+        if option == name {
+            options.get(index + 1).map(|value| value.as_str())
+        } else {
+            option.strip_prefix(name)?.strip_prefix('=')
+        }
+    })
+}
@@ -0,0 +1,127 @@
+use crate::domain::{Expr, KeywordType, Span, Token, TokenType};
+use crate::lexer::canonical_number;
+use std::rc::Rc;
+
+/// 0 - дерево не меняется, 1 - свертка констант и лишних скобок
+pub const MAX_OPT_LEVEL: u8 = 1;
+
+pub fn optimize(expr: Expr, level: u8) -> Expr {
+    if level == 0 {
+        return expr;
+    }
+    fold(expr)
+}
+
+/// сворачивает поддеревья из литералов снизу вверх, то, что зависит от идентификаторов, остается как есть
+fn fold(expr: Expr) -> Expr {
+    match expr {
+        Expr::Grouping(inner) => {
+            match fold(*inner) {
+                // скобки нужны только вокруг бинарного выражения, `((x))`, `(1)` и `(-x)` - лишние
+                binary @ Expr::Binary(..) => Expr::Grouping(Box::new(binary)),
+                inner => inner,
+            }
+        }
+        Expr::Unary(operator, right) => {
+            let right = fold(*right);
+            let folded = match (&operator._type, constant(&right)) {
+                (TokenType::MINUS, Some(Constant::Number(value))) => Some(Constant::Number(-value)),
+                (TokenType::BANG, Some(value)) => Some(Constant::Bool(!value.truthy())),
+                _ => None,
+            };
+            match folded {
+                Some(value) => literal(value, &operator),
+                None => Expr::Unary(operator, Box::new(right)),
+            }
+        }
+        Expr::Binary(left, operator, right) => {
+            let left = fold(*left);
+            let right = fold(*right);
+            let folded = match (constant(&left), constant(&right)) {
+                (Some(left), Some(right)) => binary(&operator._type, left, right),
+                _ => None,
+            };
+            match folded {
+                Some(value) => literal(value, &operator),
+                None => Expr::Binary(Box::new(left), operator, Box::new(right)),
+            }
+        }
+        literal @ Expr::Literal(..) => literal,
+    }
+}
+
+/// значение литерала, известное до выполнения
+#[derive(Debug, Clone, PartialEq)]
+enum Constant {
+    Number(f64),
+    String(String),
+    Bool(bool),
+    Nil,
+}
+
+impl Constant {
+    /// как в рантайме Lox: ложны только nil и false
+    fn truthy(&self) -> bool {
+        !matches!(self, Constant::Nil | Constant::Bool(false))
+    }
+}
+
+fn constant(expr: &Expr) -> Option<Constant> {
+    let Expr::Literal(value, token) = expr else {
+        return None;
+    };
+    match &token._type {
+        // литерал длиннее f64 парсится в inf: такой не сворачиваем, как и не конечный результат в `binary`
+        TokenType::NUMBER => value.parse().ok().filter(|number: &f64| number.is_finite()).map(Constant::Number),
+        TokenType::STRING => Some(Constant::String(value.clone())),
+        TokenType::KEYWORD(KeywordType::TRUE) => Some(Constant::Bool(true)),
+        TokenType::KEYWORD(KeywordType::FALSE) => Some(Constant::Bool(false)),
+        TokenType::KEYWORD(KeywordType::NIL) => Some(Constant::Nil),
+        _ => None,
+    }
+}
+
+/// None - если операция над такими значениями упадет в рантайме или даст не конечное число:
+/// такие выражения остаются до выполнения
+fn binary(operator: &TokenType, left: Constant, right: Constant) -> Option<Constant> {
+    use Constant::{Bool, Number, String};
+
+    let value = match (operator, left, right) {
+        (TokenType::EQUAL_EQUAL, left, right) => Bool(left == right),
+        (TokenType::BANG_EQUAL, left, right) => Bool(left != right),
+        (TokenType::PLUS, String(left), String(right)) => String(left + &right),
+        (TokenType::PLUS, Number(left), Number(right)) => Number(left + right),
+        (TokenType::MINUS, Number(left), Number(right)) => Number(left - right),
+        (TokenType::STAR, Number(left), Number(right)) => Number(left * right),
+        (TokenType::SLASH, Number(left), Number(right)) if right != 0.0 => Number(left / right),
+        (TokenType::GREATER, Number(left), Number(right)) => Bool(left > right),
+        (TokenType::GREATER_EQUAL, Number(left), Number(right)) => Bool(left >= right),
+        (TokenType::LESS, Number(left), Number(right)) => Bool(left < right),
+        (TokenType::LESS_EQUAL, Number(left), Number(right)) => Bool(left <= right),
+        _ => return None,
+    };
+
+    match value {
+        Number(number) if !number.is_finite() => None,
+        value => Some(value),
+    }
+}
+
+/// литерал со своим токеном: лексема - текст значения, позиция - у оператора, который свернули
+/// у токена нет символа: он не из лексера, и сравнивать его символ не с чем
+fn literal(value: Constant, at: &Token) -> Expr {
+    let (_type, value, lexeme) = match value {
+        Constant::Number(number) => {
+            let number = canonical_number(number);
+            (TokenType::NUMBER, number.clone(), number)
+        }
+        Constant::String(string) => (TokenType::STRING, string.clone(), format!("\"{}\"", string)),
+        Constant::Bool(true) => (TokenType::KEYWORD(KeywordType::TRUE), "true".to_string(), "true".to_string()),
+        Constant::Bool(false) => (TokenType::KEYWORD(KeywordType::FALSE), "false".to_string(), "false".to_string()),
+        Constant::Nil => (TokenType::KEYWORD(KeywordType::NIL), "nil".to_string(), "nil".to_string()),
+    };
+
+    let source: Rc<str> = Rc::from(lexeme.as_str());
+    let token = Token::new(_type, &source, Span::new(0, lexeme.len()), at._line, at._column_from, at._column_to);
+    Expr::Literal(value, token)
+}
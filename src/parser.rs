@@ -1,7 +1,10 @@
 use crate::domain::TokenType::{BANG_EQUAL, EQUAL_EQUAL, GREATER, GREATER_EQUAL, LESS, LESS_EQUAL};
-use crate::domain::{Expr, KeywordType, ParserError, ParserError::Default, Token, TokenType, Tokens, AST};
-use std::cell::Cell;
-use std::rc::Rc;
+use crate::domain::{Expr, KeywordType, LexError, ParserError, ParserError::Default, Token, TokenType, Tokens, AST};
+use std::cell::{Cell, RefCell};
+
+/// глубже рекурсивный спуск не идет: `((((...` или `!!!!...` дают ошибку, а не переполнение стека
+/// уровень - каждая скобка и каждый унарный оператор, `-(` - два уровня
+pub const MAX_NESTING: usize = 256;
 
 /// priority top-down
 /// literal -> string, number, boolean, nil
@@ -11,71 +14,76 @@ use std::rc::Rc;
 /// comparison -> > >= < <=
 /// equality -> != ==
 pub fn parse(tokens: Tokens) -> Result<AST, ParserError> {
+    parse_stream(tokens.tokens.into_iter().map(Ok))
+}
+
+/// разбор с чтением токенов по мере надобности, например прямо из `lexer::Lexer`
+/// текст с ошибкой лексера пропускается, а первая такая ошибка возвращается вместо результата
+pub fn parse_stream(tokens: impl Iterator<Item = Result<Token, LexError>> + 'static) -> Result<AST, ParserError> {
     let parser = Parser {
-        tokens: Rc::new(tokens.tokens),
+        tokens: RefCell::new(Box::new(tokens)),
+        lex_error: RefCell::new(None),
         result: Cell::new(0),
-        current: Cell::new(0),
+        previous: RefCell::new(None),
+        current: RefCell::new(None),
+        depth: Cell::new(0),
     };
-    Ok(AST { expr: expression(&parser)? })
+    parser.current.replace(parser.pull());
+
+    let expr = expression(&parser);
+    if let Some(error) = parser.lex_error.take() {
+        return Err(ParserError::Lex(error));
+    }
+    let expr = expr?;
+    // грамматика - одно выражение, все после него осталось бы непрочитанным
+    if !parser.at_end() {
+        return Err(Default("Expect end of expression.".to_string(),
+                           Box::new(parser.peek().expect("token not found")),
+                           65));
+    }
+    Ok(AST { expr })
 }
 
 /// начало парсинга токенов с переданного оффсета
 fn expression(parser: &Parser) -> Result<Expr, ParserError> {
-    equality(parser)
+    parser.nested(equality)
 }
 
 /// equality -> != ==
 fn equality(parser: &Parser) -> Result<Expr, ParserError> {
-    let expr = comparison(parser);
-
-    if parser.match_tokens(&[BANG_EQUAL, EQUAL_EQUAL]) {
-        let operator = parser.previous();
-        let right = factor(parser);
-        Ok(Expr::Binary(Box::from(expr?), operator.expect("operator not found").clone(), Box::from(right?)))
-    } else {
-        expr
-    }
+    binary(parser, &[BANG_EQUAL, EQUAL_EQUAL], comparison)
 }
 
 /// comparison -> > >= < <=
 fn comparison(parser: &Parser) -> Result<Expr, ParserError> {
-    let expr = term(parser);
-
-    if parser.match_tokens(&[GREATER, GREATER_EQUAL, LESS, LESS_EQUAL]) {
-        let operator = parser.previous();
-        let right = factor(parser);
-        Ok(Expr::Binary(Box::from(expr?), operator.expect("operator not found").clone(), Box::from(right?)))
-    } else {
-        expr
-    }
+    binary(parser, &[GREATER, GREATER_EQUAL, LESS, LESS_EQUAL], term)
 }
 
 /// term -> + -
 /// если есть + - то возрвращает Binary, иначе Unary
 fn term(parser: &Parser) -> Result<Expr, ParserError> {
-    let expr = factor(parser);
-
-    if parser.match_tokens(&[TokenType::MINUS, TokenType::PLUS]) {
-        let operator = parser.previous();
-        let right = factor(parser);
-        Ok(Expr::Binary(Box::from(expr?), operator.expect("operator not found").clone(), Box::from(right?)))
-    } else {
-        expr
-    }
+    binary(parser, &[TokenType::MINUS, TokenType::PLUS], factor)
 }
 
 /// factor -> ! *
 /// если есть ! * то возрвращает Binary, иначе Unary
 fn factor(parser: &Parser) -> Result<Expr, ParserError> {
-    let expr = unary(parser);
+    binary(parser, &[TokenType::SLASH, TokenType::STAR], unary)
+}
 
-    if parser.match_tokens(&[TokenType::SLASH, TokenType::STAR]) {
-        let operator = parser.previous();
-        let right = unary(parser);
-        Ok(Expr::Binary(Box::from(expr?), operator.expect("operator not found").clone(), Box::from(right?)))
-    } else {
-        expr
+/// левоассоциативная цепочка `operand (operator operand)*`: `1 - 2 - 3` - это `(1 - 2) - 3`
+fn binary(parser: &Parser,
+          operators: &[TokenType],
+          operand: fn(&Parser) -> Result<Expr, ParserError>) -> Result<Expr, ParserError> {
+    let mut expr = operand(parser)?;
+
+    while parser.match_tokens(operators) {
+        let operator = parser.previous().expect("operator not found");
+        let right = operand(parser)?;
+        expr = Expr::Binary(Box::from(expr), operator, Box::from(right));
     }
+
+    Ok(expr)
 }
 
 // unary -> ! -
@@ -83,8 +91,8 @@ fn factor(parser: &Parser) -> Result<Expr, ParserError> {
 fn unary(parser: &Parser) -> Result<Expr, ParserError> {
     if parser.match_tokens(&[TokenType::BANG, TokenType::MINUS]) {
         let operator = parser.previous();
-        let right = unary(parser);
-        Ok(Expr::Unary(operator.expect("operator not found").clone(), Box::from(right?)))
+        let right = parser.nested(unary);
+        Ok(Expr::Unary(operator.expect("operator not found"), Box::from(right?)))
     } else {
         primary(parser)
     }
@@ -93,7 +101,7 @@ fn unary(parser: &Parser) -> Result<Expr, ParserError> {
 /// literal -> string, number, boolean, nil, (, )
 fn primary(parser: &Parser) -> Result<Expr, ParserError> {
     if let Some(keyword) = parser.match_keyword() {
-        return Ok(Expr::Literal(keyword.0.to_string().to_lowercase(), keyword.1.clone()));
+        return Ok(Expr::Literal(keyword.0.to_string().to_lowercase(), keyword.1));
     }
     if parser.match_tokens(&[TokenType::STRING, TokenType::IDENTIFIER]) {
         get_or_ex_value("string invalid", parser, |val, token| Expr::Literal(val, token))
@@ -101,11 +109,11 @@ fn primary(parser: &Parser) -> Result<Expr, ParserError> {
         get_or_ex_value("number invalid", parser, |val, token| Expr::Literal(val, token))
     } else if parser.match_token(TokenType::LEFT_PAREN) {
         let expr = expression(parser)?;
-        parser.consume(TokenType::LEFT_PAREN, "Expect ')' after expression.")?;
+        parser.consume(TokenType::RIGHT_PAREN, "Expect ')' after expression.")?;
         Ok(Expr::Grouping(Box::from(expr)))
     } else {
         return Err(Default("token not supported".to_string(),
-                           parser.previous().expect("token not found").clone(),
+                           Box::new(parser.previous().expect("token not found")),
                            65))
     }
 }
@@ -113,43 +121,74 @@ fn primary(parser: &Parser) -> Result<Expr, ParserError> {
 fn get_or_ex_value(message: &str, parser: &Parser, convert: impl FnOnce(String, Token) -> Expr) -> Result<Expr, ParserError> {
     match parser.previous() {
         None => Err(Default(message.to_string(),
-                            parser.peek().expect("token not found").clone(),
+                            Box::new(parser.peek().expect("token not found")),
                             65)),
         Some(result) => {
-            match &result._value {
+            match result.value() {
                 None => Ok(convert("".to_string(), result.clone())),
-                Some(value) => Ok(convert(value.clone(), result.clone()))
+                Some(value) => Ok(convert(value.into_owned(), result.clone()))
             }
         }
     }
 }
 
 struct Parser {
+    tokens: RefCell<Box<dyn Iterator<Item = Result<Token, LexError>>>>,
+    /// первая ошибка лексера
+    lex_error: RefCell<Option<LexError>>,
     result: Cell<i32>,
-    current: Cell<usize>,
-    tokens: Rc<Vec<Token>>,
+    previous: RefCell<Option<Token>>,
+    /// текущий токен, следующие еще не прочитаны
+    current: RefCell<Option<Token>>,
+    depth: Cell<usize>,
 }
 
 impl Parser {
+    /// вызывает правило на уровень глубже, после MAX_NESTING уровней - ошибка
+    fn nested(&self, rule: fn(&Parser) -> Result<Expr, ParserError>) -> Result<Expr, ParserError> {
+        if self.depth.get() >= MAX_NESTING {
+            return Err(Default("Expression nesting is too deep.".to_string(),
+                               Box::new(self.previous().expect("token not found")),
+                               65));
+        }
+        self.depth.set(self.depth.get() + 1);
+        let expr = rule(self);
+        self.depth.set(self.depth.get() - 1);
+        expr
+    }
+
+    /// читает следующий токен из потока, ошибки лексера запоминает и пропускает
+    fn pull(&self) -> Option<Token> {
+        loop {
+            match self.tokens.borrow_mut().next()? {
+                Ok(token) => return Some(token),
+                Err(error) => {
+                    self.lex_error.borrow_mut().get_or_insert(error);
+                }
+            }
+        }
+    }
+
     /// отдает текущий токен и не двигает оффсет
-    fn peek(&self) -> Option<&Token> {
-        self.tokens.get(self.current.get())
+    fn peek(&self) -> Option<Token> {
+        self.current.borrow().clone()
     }
 
     /// отдает следующий токен и двигает оффсет
-    fn next(&self) -> Option<&Token> {
-        self.current.set(self.current.get() + 1);
-        self.tokens.get(self.current.get())
+    fn next(&self) -> Option<Token> {
+        let next = self.pull();
+        self.previous.replace(self.current.replace(next));
+        self.peek()
     }
 
-    /// отдает предыдущий токен
-    fn previous(&self) -> Option<&Token> {
-        self.tokens.get(self.current.get().saturating_sub(1))
+    /// отдает предыдущий токен, до первого сдвига - текущий
+    fn previous(&self) -> Option<Token> {
+        self.previous.borrow().clone().or_else(|| self.peek())
     }
 
     /// проверяет конец токенов
     fn at_end(&self) -> bool {
-        match self.tokens.get(self.current.get()) {
+        match self.current.borrow().as_ref() {
             None => false,
             Some(val) => val._type == TokenType::EOF
         }
@@ -158,17 +197,17 @@ impl Parser {
     /// проверяет совпадение текущего оффсета с типом
     fn check(&self, token_type: TokenType) -> bool {
         if self.at_end() { return false; }
-        if let Some(val) = self.peek() {
+        if let Some(val) = self.current.borrow().as_ref() {
             val._type == token_type
         } else { false }
     }
 
     /// проверяет, что текущий токен нужного типа и двигает оффсет
-    fn consume(&self, token_type: TokenType, message: &str) -> Result<&Token, ParserError> {
+    fn consume(&self, token_type: TokenType, message: &str) -> Result<Token, ParserError> {
         if self.check(token_type) {
             Ok(self.next().expect("token error"))
         } else {
-            Err(Default(message.to_string(), self.peek().expect("token error").clone(), 65))
+            Err(Default(message.to_string(), Box::new(self.peek().expect("token error")), 65))
         }
     }
 
@@ -185,23 +224,20 @@ impl Parser {
 
     /// проверяет текущий токен равен ли искомому и сдвигает оффсет, возвраащая следующий элемент
     fn match_token(&self, token: TokenType) -> bool {
-        if let Some(next) = self.peek() {
-            if token == next._type {
-                self.next();
-                return true;
-            }
+        let matched = self.current.borrow().as_ref().is_some_and(|next| next._type == token);
+        if matched {
+            self.next();
         }
-        false
+        matched
     }
 
     /// проверяет на ключевое слово
-    fn match_keyword(&self) -> Option<(KeywordType, &Token)> {
-        if let Some(next) = self.peek() {
-            if let TokenType::KEYWORD(kw) = &next._type {
-                self.next();
-                return Some((kw.clone(), next));
-            }
+    fn match_keyword(&self) -> Option<(KeywordType, Token)> {
+        let next = self.peek()?;
+        if let TokenType::KEYWORD(kw) = &next._type {
+            self.next();
+            return Some((kw.clone(), next));
         }
         None
     }
-}
\ No newline at end of file
+}
@@ -4,8 +4,12 @@ use crate::parser::parse;
 
 #[cfg(test)]
 mod test_lexer {
-    use crate::domain::{Token, TokenType};
+    use crate::domain::{LexError, Span, Token, TokenType};
+    use crate::lexer::Lexer;
     use crate::test::generate_tokens;
+    use std::borrow::Cow;
+    use std::rc::Rc;
+    use std::time::Instant;
 
     #[test]
     fn test_base_code_tokens() {
@@ -87,12 +91,273 @@ mod test_lexer {
         )
     }
 
+    #[test]
+    fn test_unicode_identifier_tokens() {
+
+        // given:
+        let code = "café _π x٣ ٣ ²";
+
+        // when:
+        let tokens = generate_tokens(code.to_string());
+
+        // then:
+        println!("{:?}", tokens.tokens);
+        assert_eq!(4, tokens.tokens.len());
+        assert_eq!(65, tokens.code);
+
+        check_tokens(
+            tokens.tokens,
+            Vec::from([
+                (TokenType::IDENTIFIER, "café"),
+                (TokenType::IDENTIFIER, "_π"),
+                (TokenType::IDENTIFIER, "x٣"),
+                (TokenType::EOF, ""),
+            ]),
+        )
+    }
+
+    #[test]
+    fn test_token_spans() {
+
+        // given:
+        let code = "ёж >= \"мир\" // comment\n 12.5";
+
+        // when:
+        let tokens = generate_tokens(code.to_string());
+
+        // then:
+        println!("{:?}", tokens.tokens);
+        assert_eq!(0, tokens.code);
+
+        for token in &tokens.tokens {
+            assert_eq!(token.lexeme(), &code[token._span.start..token._span.end]);
+        }
+        assert_eq!(Span::new(0, 4), tokens.tokens[0]._span);
+        assert_eq!(Span::new(code.len(), code.len()), tokens.tokens.last().unwrap()._span);
+    }
+
+    #[test]
+    fn test_nested_block_comment_tokens() {
+
+        // given:
+        let code = "1 /* outer /* inner\n */ still\n comment */ + 2";
+
+        // when:
+        let tokens = generate_tokens(code.to_string());
+
+        // then:
+        println!("{:?}", tokens.tokens);
+        assert_eq!(4, tokens.tokens.len());
+        assert_eq!(0, tokens.code);
+        assert_eq!(3, tokens.tokens[1]._line);
+
+        check_tokens(
+            tokens.tokens,
+            Vec::from([
+                (TokenType::NUMBER, "1"),
+                (TokenType::PLUS, "+"),
+                (TokenType::NUMBER, "2"),
+                (TokenType::EOF, ""),
+            ]),
+        )
+    }
+
+    #[test]
+    fn test_unterminated_block_comment_tokens() {
+
+        // given:
+        let code = "1\n/* open /* nested */\n2";
+
+        // when:
+        let tokens = generate_tokens(code.to_string());
+
+        // then:
+        println!("{:?}", tokens.tokens);
+        assert_eq!(2, tokens.tokens.len());
+        assert_eq!(65, tokens.code);
+    }
+
+    #[test]
+    fn test_doc_comment_trivia() {
+
+        // given:
+        let code = "/// sums\n///two numbers\n//// not a doc\nsum // plain\nx";
+
+        // when:
+        let tokens = generate_tokens(code.to_string());
+
+        // then:
+        println!("{:?}", tokens.tokens);
+        assert_eq!(0, tokens.code);
+        assert_eq!(Some("sums\ntwo numbers".to_string()), tokens.tokens[0].doc_comment());
+        assert_eq!(None, tokens.tokens[1].doc_comment());
+        assert_eq!("/// sums", &code[tokens.tokens[0]._trivia[0].span.start..tokens.tokens[0]._trivia[0].span.end]);
+    }
+
+    #[test]
+    fn test_strict_dot_number_tokens() {
+
+        // given:
+        let code = "1.2.3 1.";
+
+        // when:
+        let tokens = generate_tokens(code.to_string());
+
+        // then:
+        println!("{:?}", tokens.tokens);
+        assert_eq!(6, tokens.tokens.len());
+        assert_eq!(0, tokens.code);
+
+        check_tokens_val(
+            tokens.tokens,
+            Vec::from([
+                (TokenType::NUMBER, "1.2", Some("1.2")),
+                (TokenType::DOT, ".", None),
+                (TokenType::NUMBER, "3", Some("3.0")),
+                (TokenType::NUMBER, "1", Some("1.0")),
+                (TokenType::DOT, ".", None),
+                (TokenType::EOF, "", None)
+            ]),
+        )
+    }
+
+    #[test]
+    fn test_extended_number_tokens() {
+
+        // given:
+        let code = "0x1F 0b1010 1_000_000 1e-9 2.5E+3 12.500";
+
+        // when:
+        let tokens = generate_tokens(code.to_string());
+
+        // then:
+        println!("{:?}", tokens.tokens);
+        assert_eq!(7, tokens.tokens.len());
+        assert_eq!(0, tokens.code);
+
+        check_tokens_val(
+            tokens.tokens,
+            Vec::from([
+                (TokenType::NUMBER, "0x1F", Some("31.0")),
+                (TokenType::NUMBER, "0b1010", Some("10.0")),
+                (TokenType::NUMBER, "1_000_000", Some("1000000.0")),
+                (TokenType::NUMBER, "1e-9", Some("0.000000001")),
+                (TokenType::NUMBER, "2.5E+3", Some("2500.0")),
+                (TokenType::NUMBER, "12.500", Some("12.5")),
+                (TokenType::EOF, "", None)
+            ]),
+        )
+    }
+
+    #[test]
+    fn test_malformed_number_tokens() {
+        for code in ["0x", "0b102", "0xZ", "1__0", "1_", "1_.5", "1e999"] {
+
+            // when:
+            let tokens = generate_tokens(code.to_string());
+
+            // then:
+            println!("{:?}", tokens.tokens);
+            assert_eq!(65, tokens.code, "{}", code);
+            assert_eq!(TokenType::EOF, tokens.tokens[0]._type, "{}", code);
+        }
+    }
+
+    #[test]
+    fn test_lexeme_and_value_borrow_source() {
+
+        // given:
+        let code = "\"héllo\" 1_0.50 name";
+
+        // when:
+        let tokens = generate_tokens(code.to_string());
+
+        // then:
+        let source = &tokens.tokens[0]._source;
+        assert!(tokens.tokens.iter().all(|token| Rc::ptr_eq(source, &token._source)));
+        assert!(matches!(tokens.tokens[0].value(), Some(Cow::Borrowed("héllo"))));
+        assert_eq!(Some("10.5".into()), tokens.tokens[1].value());
+        assert_eq!(None, tokens.tokens[2].value());
+        assert_eq!("name", tokens.tokens[2].lexeme());
+    }
+
+    #[test]
+    fn test_lexer_iterator_returns_errors_as_values() {
+
+        // given:
+        let code = "1 @ \"open";
+
+        // when:
+        let items: Vec<_> = Lexer::new(code.to_string()).collect();
+
+        // then:
+        println!("{:?}", items);
+        assert_eq!(4, items.len());
+        assert_eq!("1", items[0].as_ref().unwrap().lexeme());
+        assert_eq!(Err(LexError::UnexpectedCharacter { character: '@', line: 1, span: Span::new(2, 3) }), items[1]);
+        assert_eq!(Err(LexError::UnterminatedString { line: 1, span: Span::new(4, 9) }), items[2]);
+        assert_eq!(TokenType::EOF, items[3].as_ref().unwrap()._type);
+    }
+
+    #[test]
+    fn test_lex_errors_collected_in_tokens() {
+
+        // given:
+        let code = "# 0b12\n/* open \"never closed";
+
+        // when:
+        let tokens = generate_tokens(code.to_string());
+
+        // then:
+        println!("{:?}", tokens.errors);
+        assert_eq!(65, tokens.code);
+        assert_eq!(1, tokens.tokens.len());
+        assert_eq!(vec![
+            LexError::UnexpectedCharacter { character: '#', line: 1, span: Span::new(0, 1) },
+            LexError::InvalidNumber {
+                literal: "0b12".to_string(),
+                reason: "invalid binary digit '2'".to_string(),
+                line: 1,
+                span: Span::new(2, 6),
+            },
+            LexError::UnterminatedBlockComment { line: 2, span: Span::new(7, 28) },
+        ], tokens.errors);
+        assert_eq!("Invalid number literal '0b12': invalid binary digit '2'.", tokens.errors[1].to_string());
+    }
+
+    /// cargo test --release bench_lexer_megabytes -- --ignored --nocapture
+    #[test]
+    #[ignore]
+    fn bench_lexer_megabytes() {
+
+        // given:
+        let line = "/// doc\nsome_name + 12.5e3 * (\"a string\" != 0x1F) // comment\n";
+        let small = line.repeat(1_000_000 / line.len());
+        let large = line.repeat(8_000_000 / line.len());
+
+        // when:
+        let started = Instant::now();
+        let small_tokens = generate_tokens(small.clone()).tokens.len();
+        let small_time = started.elapsed();
+
+        let started = Instant::now();
+        let large_tokens = generate_tokens(large.clone()).tokens.len();
+        let large_time = started.elapsed();
+
+        // then:
+        println!("{} bytes, {} tokens: {:?}", small.len(), small_tokens, small_time);
+        println!("{} bytes, {} tokens: {:?} ({:.1} MB/s)", large.len(), large_tokens, large_time,
+                 large.len() as f64 / large_time.as_secs_f64() / 1e6);
+        // время растет линейно: в 8 раз больше входа - не больше чем в 16 раз дольше
+        assert!(large_time < small_time * 16);
+    }
+
     fn check_tokens(tokens: Vec<Token>, expected: Vec<(TokenType, &str)>) {
         for (index, expected_token) in expected.into_iter().enumerate() {
             let token = tokens.get(index);
             assert!(token.is_some());
             assert_eq!(expected_token.0, token.unwrap()._type);
-            assert_eq!(expected_token.1.to_string(), *token.unwrap()._string)
+            assert_eq!(expected_token.1.to_string(), *token.unwrap().lexeme())
         }
     }
 
@@ -101,10 +366,10 @@ mod test_lexer {
             let token = tokens.get(index);
             assert!(token.is_some());
             assert_eq!(expected_token.0, token.unwrap()._type);
-            assert_eq!(expected_token.1.to_string(), *token.unwrap()._string);
+            assert_eq!(expected_token.1.to_string(), *token.unwrap().lexeme());
 
             if let Some(val) = expected_token.2 {
-                assert_eq!(val.to_string(), token.unwrap().clone()._value.unwrap())
+                assert_eq!(val.to_string(), token.unwrap().value().unwrap())
             }
         }
     }
@@ -112,8 +377,12 @@ mod test_lexer {
 
 #[cfg(test)]
 mod test_parser {
-    use crate::domain::{Expr, KeywordType, TokenType};
-    use crate::test::parse_tokens;
+    use crate::domain::{Expr, KeywordType, ParserError, TokenType};
+    use crate::lexer::Lexer;
+    use crate::parser::{parse_stream, MAX_NESTING};
+    use crate::test::{on_large_stack, parse_tokens};
+    use std::cell::Cell;
+    use std::rc::Rc;
 
     #[test]
     fn test_base_code_parser() {
@@ -150,6 +419,29 @@ mod test_parser {
         }
     }
 
+    #[test]
+    fn test_grouping_parser() {
+
+        // given:
+        let code = "(1 + 2) * 3";
+
+        // when:
+        let ast_result = parse_tokens(code.to_string());
+
+        // then:
+        println!("{:?}", ast_result);
+        match ast_result.unwrap().expr {
+            Expr::Binary(left, token, _) => {
+                assert_eq!(TokenType::STAR, token._type);
+                match *left {
+                    Expr::Grouping(inner) => assert!(matches!(*inner, Expr::Binary(..))),
+                    other => panic!("grouping expected: {:?}", other)
+                }
+            }
+            other => panic!("binary expected: {:?}", other)
+        }
+    }
+
     #[test]
     fn test_true_parser() {
 
@@ -221,7 +513,7 @@ mod test_parser {
 
                 match *left {
                     Expr::Literal(_, token) => {
-                        assert_eq!("BANG_EQUAL", token._string);
+                        assert_eq!("BANG_EQUAL", token.lexeme());
                         assert_eq!(TokenType::IDENTIFIER, token._type)
                     }
                     _ => assert!(false, "invalid type left")
@@ -229,7 +521,7 @@ mod test_parser {
 
                 match *right {
                     Expr::Literal(_, token) => {
-                        assert_eq!("null", token._string);
+                        assert_eq!("null", token.lexeme());
                         assert_eq!(TokenType::IDENTIFIER, token._type)
                     }
                     _ => assert!(false, "invalid type right")
@@ -239,6 +531,128 @@ mod test_parser {
         }
     }
 
+    #[test]
+    fn test_parse_stream_pulls_tokens_on_demand() {
+
+        // given:
+        let pulled = Rc::new(Cell::new(0));
+        let counter = Rc::clone(&pulled);
+        let tokens = Lexer::new("1 + 2 3 4 5".to_string()).inspect(move |_| counter.set(counter.get() + 1));
+
+        // when:
+        let ast_result = parse_stream(tokens);
+
+        // then:
+        match ast_result {
+            Err(ParserError::Default(message, token, _)) => {
+                assert_eq!("Expect end of expression.", message);
+                assert_eq!("3", token.lexeme());
+            }
+            other => panic!("trailing tokens accepted: {:?}", other)
+        }
+        // 1 + 2 и один токен, по которому видно конец выражения, остальные не прочитаны
+        assert_eq!(4, pulled.get());
+    }
+
+    #[test]
+    fn test_binary_chain_parser() {
+
+        // given:
+        let code = "1 - 2 - 3 * 4 / 5 == 6 < 7 < 8";
+
+        // when:
+        let ast_result = parse_tokens(code.to_string());
+
+        // then:
+        assert_eq!(
+            "1.0 MINUS - null 2.0 MINUS - null 3.0 STAR * null 4.0 SLASH / null 5.0 EQUAL_EQUAL == null 6.0 LESS < null 7.0 LESS < null 8.0",
+            ast_result.unwrap().expr.to_string()
+        );
+
+        // (1 - 2) - 3, а не 1 - (2 - 3)
+        match parse_tokens("1 - 2 - 3".to_string()).unwrap().expr {
+            Expr::Binary(left, _, right) => {
+                assert!(matches!(*left, Expr::Binary(..)));
+                assert!(matches!(*right, Expr::Literal(..)));
+            }
+            other => panic!("binary expected: {:?}", other)
+        }
+    }
+
+    #[test]
+    fn test_trailing_tokens_parser() {
+        for code in ["1; \"a\" - 1", "1\nx == x", "print x == x;"] {
+
+            // when:
+            let ast_result = parse_tokens(code.to_string());
+
+            // then:
+            match ast_result {
+                Err(ParserError::Default(message, _, code)) => {
+                    assert_eq!("Expect end of expression.", message);
+                    assert_eq!(65, code);
+                }
+                other => panic!("trailing tokens accepted: {:?}", other)
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_stream_returns_lex_error() {
+
+        // given:
+        let code = "1 + @ 2";
+
+        // when:
+        let ast_result = parse_stream(Lexer::new(code.to_string()));
+
+        // then:
+        match ast_result {
+            Err(ParserError::Lex(error)) => assert_eq!("Unexpected character: @", error.to_string()),
+            other => panic!("lex error expected: {:?}", other)
+        }
+    }
+
+    #[test]
+    fn test_deep_nesting_parser() {
+        for code in ["(".repeat(10_000), "!".repeat(10_000) + "true", "-(".repeat(5_000)] {
+            on_large_stack(move || {
+
+                // when:
+                let ast_result = parse_tokens(code);
+
+                // then:
+                match ast_result {
+                    Err(ParserError::Default(message, _, code)) => {
+                        assert_eq!("Expression nesting is too deep.", message);
+                        assert_eq!(65, code);
+                    }
+                    other => panic!("nesting limit not reached: {:?}", other)
+                }
+            });
+        }
+    }
+
+    #[test]
+    fn test_nesting_below_limit_parser() {
+
+        // given:
+        let depth = MAX_NESTING - 1;
+        let grouped = "(".repeat(depth) + "1" + &")".repeat(depth);
+        let negated = "-(".repeat(depth / 2) + "1" + &")".repeat(depth / 2);
+
+        for code in [grouped, negated] {
+            on_large_stack(move || {
+
+                // when:
+                let ast_result = parse_tokens(code);
+
+                // then:
+                assert!(ast_result.is_ok());
+            });
+        }
+    }
+
     #[test]
     fn test_bang_bang_equal_parser() {
 
@@ -262,7 +676,7 @@ mod test_parser {
 
                 match *left {
                     Expr::Literal(_, token) => {
-                        assert_eq!("BANG_EQUAL", token._string);
+                        assert_eq!("BANG_EQUAL", token.lexeme());
                         assert_eq!(TokenType::IDENTIFIER, token._type)
                     }
                     _ => assert!(false, "invalid type left")
@@ -270,7 +684,7 @@ mod test_parser {
 
                 match *right {
                     Expr::Literal(_, token) => {
-                        assert_eq!("null", token._string);
+                        assert_eq!("null", token.lexeme());
                         assert_eq!(TokenType::IDENTIFIER, token._type)
                     }
                     _ => assert!(false, "invalid type right")
@@ -281,12 +695,1142 @@ mod test_parser {
     }
 }
 
-fn parse_tokens(code: String) -> Result<AST, ParserError> {
-    let tokens = generate_tokens(code);
-    println!("{:?}", tokens);
-    parse(tokens)
-}
+#[cfg(test)]
+mod test_cst {
+    use crate::cst::{build, SyntaxElement, SyntaxKind};
+    use crate::domain::TriviaKind;
+    use crate::lexer::{tokenize_code, tokenize_lossless};
 
-fn generate_tokens(code: String) -> Tokens {
-    tokenize_code(code)
+    const MESSY_CODE: &str = "/// doc\n  -(1 +\t2) * \"ёж\" // tail\r\n/* a /* b */ */ ) @ 0x 1 == 1 \"open\n";
+
+    #[test]
+    fn test_lossless_round_trip() {
+
+        // given:
+        let code = MESSY_CODE;
+
+        // when:
+        let tokens = tokenize_lossless(code.to_string());
+        let tree = build(tokens.clone());
+
+        // then:
+        println!("{:?}", tree);
+        assert_eq!(65, tokens.code);
+        assert_eq!(code, tree.text());
+
+        let kinds: Vec<TriviaKind> = tokens.tokens.iter()
+            .flat_map(|token| token._trivia.iter().map(|trivia| trivia.kind.clone()))
+            .collect();
+        for kind in [TriviaKind::Whitespace, TriviaKind::LineComment, TriviaKind::BlockComment,
+                     TriviaKind::DocComment, TriviaKind::Skipped] {
+            assert!(kinds.contains(&kind), "{:?}", kind);
+        }
+    }
+
+    #[test]
+    fn test_lossless_keeps_tokens() {
+
+        // given:
+        let code = MESSY_CODE;
+
+        // when:
+        let plain = tokenize_code(code.to_string());
+        let lossless = tokenize_lossless(code.to_string());
+
+        // then:
+        assert_eq!(plain.tokens.len(), lossless.tokens.len());
+        for (plain, lossless) in plain.tokens.iter().zip(lossless.tokens.iter()) {
+            assert_eq!(plain.to_string(), lossless.to_string());
+            assert_eq!(plain._span, lossless._span);
+            assert!(plain._trivia.iter().all(|trivia| trivia.kind == TriviaKind::DocComment));
+        }
+    }
+
+    #[test]
+    fn test_cst_structure() {
+
+        // given:
+        let code = "1 + 2 * -x ) 3";
+
+        // when:
+        let tree = build(tokenize_lossless(code.to_string()));
+
+        // then:
+        println!("{:?}", tree);
+        let kinds: Vec<SyntaxKind> = tree.children.iter()
+            .map(|child| match child {
+                SyntaxElement::Node(node) => node.kind.clone(),
+                SyntaxElement::Token(_) => SyntaxKind::Root,
+            })
+            .collect();
+        assert_eq!(vec![SyntaxKind::Binary, SyntaxKind::Error, SyntaxKind::Literal, SyntaxKind::Root], kinds);
+
+        match &tree.children[0] {
+            SyntaxElement::Node(node) => {
+                assert_eq!("1", node.tokens()[0].lexeme());
+                match &node.children[2] {
+                    SyntaxElement::Node(right) => {
+                        assert_eq!(SyntaxKind::Binary, right.kind);
+                        assert_eq!(" 2 * -x", right.text());
+                    }
+                    _ => panic!("invalid right operand")
+                }
+            }
+            _ => panic!("invalid type main")
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_formatter {
+    use crate::domain::ParserError;
+    use crate::formatter::{format, DEFAULT_WIDTH};
+    use crate::lexer::tokenize_lossless;
+    use crate::test::on_large_stack;
+
+    fn format_code(code: &str, width: usize) -> Result<String, ParserError> {
+        format(tokenize_lossless(code.to_string()), width)
+    }
+
+    #[test]
+    fn test_format_spacing_and_braces() {
+
+        // given:
+        let code = "/// doc\n   1+2*  -x   // trailing\n\n\n\nvar   x = (  1+2 )  ;\n{ foo(1), 2 ; /* inline */ bar }";
+
+        // when:
+        let formatted = format_code(code, DEFAULT_WIDTH);
+
+        // then:
+        println!("{:?}", formatted);
+        assert_eq!(
+            "/// doc\n1 + 2 * -x // trailing\n\nvar x = (1 + 2);\n{\n    foo(1), 2; /* inline */ bar\n}\n",
+            formatted.unwrap()
+        );
+    }
+
+    #[test]
+    fn test_format_wraps_long_binary() {
+
+        // given:
+        let code = "\"aaaaaaaa\" + \"bbbbbbbb\" - \"cccccccc\" == 1 * 2";
+
+        // when:
+        let formatted = format_code(code, 30).unwrap();
+
+        // then:
+        println!("{}", formatted);
+        assert_eq!("\"aaaaaaaa\"\n    + \"bbbbbbbb\"\n    - \"cccccccc\"\n    == 1 * 2\n", formatted);
+        assert_eq!(formatted, format_code(&formatted, 30).unwrap());
+    }
+
+    #[test]
+    fn test_format_is_idempotent() {
+
+        // given:
+        let code = "/* head */\n!(1 >= 2) // a\n// own line\n{ {\n\"s\" } }";
+
+        // when:
+        let formatted = format_code(code, DEFAULT_WIDTH).unwrap();
+
+        // then:
+        println!("{}", formatted);
+        assert_eq!(formatted, format_code(&formatted, DEFAULT_WIDTH).unwrap());
+        for comment in ["/* head */", "// a", "// own line"] {
+            assert!(formatted.contains(comment), "{}", comment);
+        }
+
+        // комментарий перед `}` - внутри блока, пустой блок с комментарием раскрывается как `{ y }`
+        let cases = [
+            ("{\n// c\n}", "{\n    // c\n}\n"),
+            ("{ /* c */ }", "{\n    /* c */\n}\n"),
+            ("{ y }", "{\n    y\n}\n"),
+            ("{ { 1\n// c\n} }", "{\n    {\n        1\n        // c\n    }\n}\n"),
+        ];
+        for (code, expected) in cases {
+            let formatted = format_code(code, DEFAULT_WIDTH).unwrap();
+            assert_eq!(expected, formatted, "{:?}", code);
+            assert_eq!(formatted, format_code(&formatted, DEFAULT_WIDTH).unwrap(), "{:?}", code);
+        }
+    }
+
+    #[test]
+    fn test_format_deep_nesting() {
+
+        for code in ["(".repeat(10_000) + &"!".repeat(10_000), "!".repeat(100_000), "-(".repeat(5_000)] {
+            on_large_stack(move || {
+
+                // when:
+                let formatted = format_code(&code, DEFAULT_WIDTH);
+
+                // then:
+                match formatted {
+                    Err(ParserError::Default(message, _, code)) => {
+                        assert_eq!("Expression nesting is too deep.", message);
+                        assert_eq!(65, code);
+                    }
+                    other => panic!("nesting limit not reached: {:?}", other)
+                }
+            });
+        }
+    }
+
+    #[test]
+    fn test_format_parenthesized_token_runs() {
+
+        // given:
+        let cases = [
+            ("for (var i = 0;i<3;i = i+1) print i;", "for (var i = 0; i < 3; i = i + 1) print i;\n"),
+            ("print clock();", "print clock();\n"),
+            ("while (!done) { step(); }", "while (!done) {\n    step();\n}\n"),
+            ("print f(1 ,2);", "print f(1, 2);\n"),
+            ("fun f(a,b){ a; }", "fun f(a, b) {\n    a;\n}\n"),
+            ("if (a and b) { x; } else { y; }", "if (a and b) {\n    x;\n} else {\n    y;\n}\n"),
+        ];
+
+        for (code, expected) in cases {
+
+            // when:
+            let formatted = format_code(code, DEFAULT_WIDTH);
+
+            // then:
+            assert_eq!(expected, formatted.unwrap(), "{:?}", code);
+        }
+    }
+
+    #[test]
+    fn test_format_unbalanced_paren() {
+        for code in ["f(1, 2", "(var x"] {
+
+            // when:
+            let formatted = format_code(code, DEFAULT_WIDTH);
+
+            // then:
+            match formatted {
+                Err(ParserError::Default(message, _, code)) => {
+                    assert_eq!("Expect ')' after expression.", message);
+                    assert_eq!(65, code);
+                }
+                other => panic!("unbalanced paren formatted: {:?}", other)
+            }
+        }
+    }
+
+    #[test]
+    fn test_format_incomplete_expression() {
+
+        // given:
+        let code = "(1 + 2";
+
+        // when:
+        let formatted = format_code(code, DEFAULT_WIDTH);
+
+        // then:
+        println!("{:?}", formatted);
+        match formatted {
+            Err(ParserError::Default(message, token, code)) => {
+                assert_eq!("Expect ')' after expression.", message);
+                assert_eq!("2", token.lexeme());
+                assert_eq!(65, code);
+            }
+            other => panic!("incomplete expression formatted: {:?}", other)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_linter {
+    use crate::lexer::tokenize_lossless;
+    use crate::linter::{lint, Diagnostic, LintConfig, Severity};
+    use crate::parser::parse;
+
+    fn lint_code(code: &str, config: &LintConfig) -> Vec<Diagnostic> {
+        let tokens = tokenize_lossless(code.to_string());
+        let ast = parse(tokens.clone()).expect("parse failed");
+        lint(&ast, &tokens, config)
+    }
+
+    #[test]
+    fn test_lint_reads_whole_input() {
+
+        // given:
+        let chained = "1 + 2 + (x == x)";
+        let trailing = ["1\nx == x", "print x == x;"];
+
+        // when:
+        let diagnostics = lint_code(chained, &LintConfig::default());
+
+        // then:
+        assert_eq!(1, diagnostics.len());
+        assert_eq!("self-comparison", diagnostics[0].rule);
+        // непрочитанный хвост - ошибка разбора, а не чистый файл
+        for code in trailing {
+            assert!(parse(tokenize_lossless(code.to_string())).is_err(), "{:?}", code);
+        }
+    }
+
+    #[test]
+    fn test_self_comparison() {
+
+        // given:
+        let code = "(a + 1) != (a + 1)";
+
+        // when:
+        let diagnostics = lint_code(code, &LintConfig::default());
+
+        // then:
+        println!("{:?}", diagnostics);
+        assert_eq!(1, diagnostics.len());
+        assert_eq!("self-comparison", diagnostics[0].rule);
+        assert_eq!(Severity::Warn, diagnostics[0].severity);
+        assert_eq!("[line 1] Warning: both sides of '!=' are the same expression [self-comparison]",
+                   diagnostics[0].to_string());
+    }
+
+    #[test]
+    fn test_different_sides_are_fine() {
+
+        // given:
+        let code = "a == b";
+
+        // when:
+        let diagnostics = lint_code(code, &LintConfig::default());
+
+        // then:
+        assert!(diagnostics.is_empty());
+    }
+
+    #[test]
+    fn test_inline_allow_comment() {
+
+        // given:
+        let same_line = "x == x // lox-allow(self-comparison)";
+        let line_above = "// lox-allow(other, self-comparison)\nx == x";
+        let other_rule = "x == x // lox-allow(other)";
+
+        // when:
+        let config = LintConfig::default();
+
+        // then:
+        assert!(lint_code(same_line, &config).is_empty());
+        assert!(lint_code(line_above, &config).is_empty());
+        assert_eq!(1, lint_code(other_rule, &config).len());
+    }
+
+    #[test]
+    fn test_config_severity() {
+
+        // given:
+        let deny = LintConfig::parse("[lint]\nself-comparison = \"deny\"").unwrap();
+        let allow = LintConfig::parse("[lint]\nself-comparison = \"allow\"").unwrap();
+
+        // when:
+        let denied = lint_code("x == x", &deny);
+        let allowed = lint_code("x == x", &allow);
+
+        // then:
+        assert_eq!(Severity::Deny, denied[0].severity);
+        assert!(allowed.is_empty());
+        assert!(LintConfig::parse("[lint]\nunknown-rule = \"deny\"").is_err());
+        assert!(LintConfig::parse("[lint]\nself-comparison = \"loud\"").is_err());
+    }
+}
+
+#[cfg(test)]
+mod test_lsp {
+    use crate::lsp::serve;
+    use serde_json::{json, Value};
+    use std::io::Cursor;
+
+    const URI: &str = "file:///test.lox";
+
+    /// прогоняет сообщения клиента через сервер и возвращает все ответы и уведомления
+    fn run_session(messages: Vec<Value>) -> Vec<Value> {
+        let mut input = Vec::new();
+        for message in messages {
+            input.extend(frame(&message.to_string()));
+        }
+        run_frames(input)
+    }
+
+    fn frame(body: &str) -> Vec<u8> {
+        format!("Content-Length: {}\r\n\r\n{}", body.len(), body).into_bytes()
+    }
+
+    fn run_frames(input: Vec<u8>) -> Vec<Value> {
+        let mut output = Vec::new();
+        serve(Cursor::new(input), &mut output).expect("server failed");
+
+        let output = String::from_utf8(output).unwrap();
+        output.split("Content-Length: ")
+            .filter(|frame| !frame.is_empty())
+            .map(|frame| serde_json::from_str(frame.split_once("\r\n\r\n").unwrap().1).unwrap())
+            .collect()
+    }
+
+    fn open(text: &str) -> Vec<Value> {
+        vec![
+            json!({ "jsonrpc": "2.0", "id": 1, "method": "initialize", "params": {} }),
+            json!({ "jsonrpc": "2.0", "method": "initialized", "params": {} }),
+            json!({ "jsonrpc": "2.0", "method": "textDocument/didOpen",
+                    "params": { "textDocument": { "uri": URI, "languageId": "lox", "version": 1, "text": text } } }),
+        ]
+    }
+
+    fn request(id: i32, method: &str, line: i32, character: i32, extra: Value) -> Value {
+        let mut params = json!({ "textDocument": { "uri": URI }, "position": { "line": line, "character": character } });
+        if let Value::Object(extra) = extra {
+            params.as_object_mut().unwrap().extend(extra);
+        }
+        json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params })
+    }
+
+    fn response(messages: &[Value], id: i32) -> &Value {
+        messages.iter().find(|message| message["id"] == id).expect("no response")
+    }
+
+    fn diagnostics(messages: &[Value]) -> Vec<&Value> {
+        messages.iter()
+            .filter(|message| message["method"] == "textDocument/publishDiagnostics")
+            .collect()
+    }
+
+    #[test]
+    fn test_invalid_frames_answered_with_parse_error() {
+
+        // given:
+        let mut input = frame("{not json");
+        input.extend(b"Content-Type: application/vscode-jsonrpc\r\n\r\n");
+        input.extend(frame(&json!({ "jsonrpc": "2.0", "id": 1, "method": "initialize", "params": {} }).to_string()));
+        input.extend(frame(&json!({ "jsonrpc": "2.0", "method": "exit" }).to_string()));
+
+        // when:
+        let output = run_frames(input);
+
+        // then:
+        println!("{:#?}", output);
+        assert_eq!(3, output.len());
+        for error in &output[..2] {
+            assert_eq!(Value::Null, error["id"]);
+            assert_eq!(-32700, error["error"]["code"]);
+        }
+        assert!(response(&output, 1)["result"]["capabilities"].is_object());
+    }
+
+    #[test]
+    fn test_diagnostics_on_open_and_change() {
+
+        // given:
+        let mut messages = open("1 + @\n\"open");
+        messages.push(json!({ "jsonrpc": "2.0", "method": "textDocument/didChange",
+            "params": { "textDocument": { "uri": URI, "version": 2 }, "contentChanges": [{ "text": "(1 + 2" }] } }));
+        messages.push(json!({ "jsonrpc": "2.0", "method": "textDocument/didChange",
+            "params": { "textDocument": { "uri": URI, "version": 3 }, "contentChanges": [{ "text": "(1 + 2)" }] } }));
+        messages.push(json!({ "jsonrpc": "2.0", "id": 9, "method": "shutdown" }));
+        messages.push(json!({ "jsonrpc": "2.0", "method": "exit" }));
+
+        // when:
+        let output = run_session(messages);
+
+        // then:
+        println!("{:#?}", output);
+        assert!(response(&output, 1)["result"]["capabilities"]["hoverProvider"].as_bool().unwrap());
+        assert_eq!(Value::Null, response(&output, 9)["result"]);
+
+        let published = diagnostics(&output);
+        assert_eq!(3, published.len());
+
+        let opened = published[0]["params"]["diagnostics"].as_array().unwrap();
+        assert_eq!(2, opened.len());
+        assert_eq!("Unexpected character: @", opened[0]["message"]);
+        assert_eq!(json!({ "start": { "line": 0, "character": 4 }, "end": { "line": 0, "character": 5 } }), opened[0]["range"]);
+        assert_eq!("Unterminated string.", opened[1]["message"]);
+
+        let changed = published[1]["params"]["diagnostics"].as_array().unwrap();
+        assert_eq!(1, changed.len());
+        assert_eq!("Expect ')' after expression.", changed[0]["message"]);
+
+        assert!(published[2]["params"]["diagnostics"].as_array().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_diagnostics_of_statements() {
+
+        // given:
+        let mut messages = open("print 1;\nfoo(a, b);");
+        messages.push(json!({ "jsonrpc": "2.0", "method": "textDocument/didChange",
+            "params": { "textDocument": { "uri": URI, "version": 2 }, "contentChanges": [
+                { "range": { "start": { "line": 1, "character": 8 }, "end": { "line": 1, "character": 9 } }, "text": "" },
+            ] } }));
+
+        // when:
+        let output = run_session(messages);
+
+        // then:
+        println!("{:#?}", output);
+        let published = diagnostics(&output);
+        assert_eq!(2, published.len());
+        assert!(published[0]["params"]["diagnostics"].as_array().unwrap().is_empty());
+
+        let changed = published[1]["params"]["diagnostics"].as_array().unwrap();
+        assert_eq!(1, changed.len());
+        assert_eq!("Expect ')' after expression.", changed[0]["message"]);
+    }
+
+    #[test]
+    fn test_incremental_changes() {
+
+        // given:
+        let mut messages = open("счёт + 1\n2");
+        messages.push(json!({ "jsonrpc": "2.0", "method": "textDocument/didChange",
+            "params": { "textDocument": { "uri": URI, "version": 2 }, "contentChanges": [
+                { "range": { "start": { "line": 0, "character": 0 }, "end": { "line": 0, "character": 4 } }, "text": "x" },
+                { "range": { "start": { "line": 1, "character": 0 }, "end": { "line": 1, "character": 0 } }, "text": "x * " },
+            ] } }));
+        messages.push(request(2, "textDocument/references", 0, 0, json!({})));
+        messages.push(request(3, "textDocument/hover", 1, 5, json!({})));
+
+        // when:
+        let output = run_session(messages);
+
+        // then:
+        println!("{:#?}", output);
+        assert_eq!(2, response(&output, 1)["result"]["capabilities"]["textDocumentSync"]);
+        let references = response(&output, 2)["result"].as_array().unwrap();
+        assert_eq!(2, references.len());
+        assert_eq!(json!({ "line": 1, "character": 0 }), references[1]["range"]["start"]);
+        assert_eq!("number `2.0`", response(&output, 3)["result"]["contents"]["value"]);
+    }
+
+    #[test]
+    fn test_ranged_change_out_of_bounds_keeps_document() {
+
+        // given:
+        let mut messages = open("abc + 1");
+        messages.push(json!({ "jsonrpc": "2.0", "method": "textDocument/didChange",
+            "params": { "textDocument": { "uri": URI, "version": 2 }, "contentChanges": [
+                { "range": { "start": { "line": 5, "character": 0 }, "end": { "line": 5, "character": 0 } }, "text": " + zz" },
+                { "range": { "start": { "line": "0", "character": 0 }, "end": { "line": 0, "character": 3 } }, "text": "" },
+            ] } }));
+        messages.push(request(2, "textDocument/hover", 0, 0, json!({})));
+        messages.push(request(3, "textDocument/hover", 0, 10, json!({})));
+
+        // when:
+        let output = run_session(messages);
+
+        // then:
+        println!("{:#?}", output);
+        assert_eq!("global `abc`", response(&output, 2)["result"]["contents"]["value"]);
+        assert_eq!("global `zz`", response(&output, 3)["result"]["contents"]["value"]);
+    }
+
+    #[test]
+    fn test_hover_references_and_rename() {
+
+        // given:
+        let mut messages = open("/// counter\nсчёт + 1 == счёт * 0x10");
+        messages.push(request(2, "textDocument/hover", 1, 1, json!({})));
+        messages.push(request(3, "textDocument/hover", 1, 20, json!({})));
+        messages.push(request(4, "textDocument/references", 1, 12, json!({ "context": { "includeDeclaration": true } })));
+        messages.push(request(5, "textDocument/rename", 1, 0, json!({ "newName": "total" })));
+        messages.push(request(6, "textDocument/rename", 1, 0, json!({ "newName": "while" })));
+        messages.push(request(7, "textDocument/definition", 1, 0, json!({})));
+
+        // when:
+        let output = run_session(messages);
+
+        // then:
+        println!("{:#?}", output);
+        assert_eq!("global `счёт`\n\ncounter", response(&output, 2)["result"]["contents"]["value"]);
+        assert_eq!("number `16.0`", response(&output, 3)["result"]["contents"]["value"]);
+
+        let references = response(&output, 4)["result"].as_array().unwrap();
+        assert_eq!(2, references.len());
+        assert_eq!(json!({ "line": 1, "character": 12 }), references[1]["range"]["start"]);
+
+        let edits = response(&output, 5)["result"]["changes"][URI].as_array().unwrap();
+        assert_eq!(2, edits.len());
+        assert_eq!("total", edits[0]["newText"]);
+
+        assert_eq!(-32602, response(&output, 6)["error"]["code"]);
+        assert_eq!(-32601, response(&output, 7)["error"]["code"]);
+    }
+}
+
+#[cfg(test)]
+mod test_highlight {
+    use crate::domain::Span;
+    use crate::highlight::{classify, to_html, to_json, HighlightKind};
+    use crate::lexer::tokenize_lossless;
+
+    #[test]
+    fn test_classify_tokens_and_comments() {
+
+        // given:
+        let code = "// hi\nwhile x < 0x1F /* c */ == \"s\"";
+
+        // when:
+        let highlights = classify(&tokenize_lossless(code.to_string()));
+
+        // then:
+        println!("{:?}", highlights);
+        let kinds: Vec<(HighlightKind, &str, i32)> = highlights.iter()
+            .map(|highlight| (highlight.kind.clone(), &code[highlight.span.start..highlight.span.end], highlight.line))
+            .collect();
+        assert_eq!(vec![
+            (HighlightKind::Comment, "// hi", 1),
+            (HighlightKind::Keyword, "while", 2),
+            (HighlightKind::Global, "x", 2),
+            (HighlightKind::Number, "0x1F", 2),
+            (HighlightKind::Comment, "/* c */", 2),
+            (HighlightKind::String, "\"s\"", 2),
+        ], kinds);
+        assert_eq!(Span::new(0, 5), highlights[0].span);
+    }
+
+    #[test]
+    fn test_json_and_html_output() {
+
+        // given:
+        let code = "a < \"<b>\"";
+        let highlights = classify(&tokenize_lossless(code.to_string()));
+
+        // when:
+        let json = to_json(&highlights);
+        let html = to_html(code, &highlights, "a&b.lox");
+
+        // then:
+        println!("{}\n{}", json, html);
+        assert_eq!(r#"[{"end":1,"kind":"global","line":1,"start":0},{"end":9,"kind":"string","line":1,"start":4}]"#, json);
+        assert!(html.contains("<title>a&amp;b.lox</title>"));
+        assert!(html.contains(r#"<span class="global">a</span> &lt; <span class="string">&quot;&lt;b&gt;&quot;</span>"#));
+    }
+}
+
+#[cfg(test)]
+mod test_incremental {
+    use crate::cst::build;
+    use crate::domain::{Span, Token, Tokens};
+    use crate::incremental::{Document, TextEdit};
+    use crate::interner::Symbol;
+    use crate::lexer::tokenize_lossless;
+    use std::collections::HashMap;
+
+    const PIECES: [&str; 32] = [
+        "1", "23", ".", "e", "E", "+", "-", "5", "\"", "ab", "é", "счёт", " ", "\n", "\n", "/", "*", "(", ")",
+        "=", "!", "<", "#", "0x", "0b", "_", "//", "/*", "*/", "///", "true", "and",
+    ];
+
+    /// xorshift64, чтобы последовательность правок воспроизводилась по seed
+    struct Random(u64);
+
+    impl Random {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn below(&mut self, bound: usize) -> usize {
+            (self.next() % bound as u64) as usize
+        }
+    }
+
+    /// случайная граница символа в text
+    fn boundary(random: &mut Random, text: &str) -> usize {
+        let chars = text.chars().count();
+        text.char_indices().map(|(index, _)| index).chain([text.len()]).nth(random.below(chars + 1)).unwrap()
+    }
+
+    /// токены без символов и для каждого символа - индекс первого токена с ним:
+    /// интернер документа хранит строки прошлых правок до пересборки, поэтому номера символов отличаются от нового разбора
+    fn split_symbols(tokens: &[Token]) -> (Vec<Token>, Vec<Option<usize>>) {
+        let mut first: HashMap<Symbol, usize> = HashMap::new();
+        let classes = tokens.iter().enumerate()
+            .map(|(index, token)| token._symbol.map(|symbol| *first.entry(symbol).or_insert(index)))
+            .collect();
+        let tokens = tokens.iter()
+            .map(|token| Token { _symbol: None, ..token.clone() })
+            .collect();
+        (tokens, classes)
+    }
+
+    fn assert_same_as_full_parse(document: &Document, context: &str) {
+        let tokens = tokenize_lossless(document.text().to_string());
+        assert_eq!(split_symbols(&tokens.tokens), split_symbols(document.tokens()), "{}", context);
+        assert_eq!(tokens.errors, document.errors(), "{}", context);
+        assert_eq!(build(Tokens { code: 0, tokens: document.tokens().to_vec(), errors: Vec::new() }), *document.tree(), "{}", context);
+        assert_eq!(document.text(), document.tree().text(), "{}", context);
+    }
+
+    #[test]
+    fn test_random_edits_match_full_parse() {
+        for seed in 1..=20u64 {
+
+            // given:
+            let mut random = Random(seed.wrapping_mul(0x9E37_79B9_7F4A_7C15));
+            let mut document = Document::new("(1 + 2) * x == \"s\" // c\n/* b */ -3.5e2 != счёт\n0x1F and @".to_string());
+            assert_same_as_full_parse(&document, "initial");
+
+            for step in 0..200 {
+
+                // when:
+                let text = document.text().to_string();
+                let start = boundary(&mut random, &text);
+                let end = start + boundary(&mut random, &text[start..].chars().take(6).collect::<String>());
+                let replacement: String = (0..random.below(4)).map(|_| PIECES[random.below(PIECES.len())]).collect();
+                let edit = TextEdit { span: Span::new(start, end), text: replacement };
+                document.edit(&edit);
+
+                // then:
+                let context = format!("seed {} step {}: {:?} on {:?}", seed, step, edit, text);
+                assert_same_as_full_parse(&document, &context);
+            }
+        }
+    }
+
+    #[test]
+    fn test_edit_relexes_only_around_change() {
+
+        // given:
+        let code = "a + 1 * (b - 2)\n".repeat(1_000);
+        let mut document = Document::new(code.clone());
+        let middle = code.len() / 2 - code.len() / 2 % 16;
+
+        // when:
+        let relexed = document.edit(&TextEdit { span: Span::new(middle + 4, middle + 5), text: "42".to_string() });
+
+        // then:
+        // колонки до конца строки сдвинулись, поэтому заново разобрана только ее часть после правки
+        assert!(relexed.len() <= 9, "{:?}", relexed);
+        assert!(document.text()[middle..].starts_with("a + 42 * (b - 2)\n"));
+        assert_same_as_full_parse(&document, "middle edit");
+    }
+
+    #[test]
+    fn test_edit_opening_comment_relexes_to_end() {
+
+        // given:
+        let mut document = Document::new("1 + 2\n3 * 4\n5".to_string());
+
+        // when:
+        let relexed = document.edit(&TextEdit { span: Span::new(6, 6), text: "/*".to_string() });
+
+        // then:
+        // `2` в пределах LOOKAHEAD от правки, поэтому разбирается заново вместе с хвостом
+        assert_eq!(2..4, relexed);
+        assert_eq!(1, document.errors().len());
+        assert_same_as_full_parse(&document, "open comment");
+    }
+
+    #[test]
+    fn test_document_interner_stays_bounded() {
+
+        // given:
+        let mut document = Document::new("name + x".to_string());
+
+        // when:
+        for i in 0..1000 {
+            let end = document.text().len();
+            document.edit(&TextEdit { span: Span::new(7, end), text: format!("x{}", i) });
+        }
+
+        // then:
+        assert_eq!("name + x999", document.text());
+        assert!(document.interned() <= 64, "{}", document.interned());
+        assert_same_as_full_parse(&document, "after typing");
+        let tokens = document.tokens();
+        assert_ne!(tokens[0]._symbol, tokens[2]._symbol);
+    }
+}
+
+#[cfg(test)]
+mod test_interner {
+    use crate::domain::{Span, Token, TokenType};
+    use crate::incremental::{Document, TextEdit};
+    use crate::interner::{Interner, Symbol};
+    use crate::lexer::Lexer;
+    use crate::test::generate_tokens;
+    use std::collections::HashMap;
+    use std::hint::black_box;
+    use std::time::Instant;
+
+    #[test]
+    fn test_intern_same_string_once() {
+
+        // given:
+        let mut interner = Interner::default();
+        let first = interner.intern("counter");
+
+        // when:
+        let second = interner.intern(&String::from("counter"));
+        let other = interner.intern("count");
+
+        // then:
+        assert_eq!(first, second);
+        assert_ne!(first, other);
+        assert_eq!("counter", interner.resolve(first));
+    }
+
+    #[test]
+    fn test_lexer_interns_identifiers_and_strings() {
+
+        // given:
+        let code = "name + \"name\" + name + while + 1";
+
+        // when:
+        let mut lexer = Lexer::new(code.to_string());
+        let tokens: Vec<Token> = lexer.by_ref().map(Result::unwrap).collect();
+        let mut interner = lexer.into_interner();
+
+        // then:
+        let symbols: Vec<(TokenType, Option<Symbol>)> = tokens.iter()
+            .filter(|token| token._type != TokenType::PLUS)
+            .map(|token| (token._type.clone(), token._symbol))
+            .collect();
+        println!("{:?}", symbols);
+        assert_eq!(Some(interner.intern("name")), symbols[0].1);
+        assert_eq!(symbols[0].1, symbols[1].1);
+        assert_eq!(symbols[0].1, symbols[2].1);
+        assert_eq!(None, symbols[3].1);
+        assert_eq!(None, symbols[4].1);
+    }
+
+    #[test]
+    fn test_document_keeps_symbols_across_edits() {
+
+        // given:
+        let mut document = Document::new("name + 1".to_string());
+
+        // when:
+        document.edit(&TextEdit { span: Span::new(8, 8), text: " + name + nam".to_string() });
+
+        // then:
+        let tokens = document.tokens();
+        let identifiers: Vec<&Token> = tokens.iter()
+            .filter(|token| token._type == TokenType::IDENTIFIER)
+            .collect();
+        assert_eq!(3, identifiers.len());
+        assert_eq!(identifiers[0]._symbol, identifiers[1]._symbol);
+        assert_ne!(identifiers[0]._symbol, identifiers[2]._symbol);
+    }
+
+    /// cargo test --release bench_identifier_lookup -- --ignored --nocapture
+    #[test]
+    #[ignore]
+    fn bench_identifier_lookup() {
+
+        // given:
+        let code: String = (0..20_000).map(|i| format!("some_long_variable_name_{} + ", i % 200)).collect();
+        let tokens = generate_tokens(code + "x");
+        let identifiers: Vec<_> = tokens.tokens.iter()
+            .filter(|token| token._type == TokenType::IDENTIFIER)
+            .collect();
+        let by_string: HashMap<String, usize> = identifiers.iter()
+            .enumerate()
+            .map(|(index, token)| (token.lexeme().to_string(), index))
+            .collect();
+        let by_symbol: HashMap<Symbol, usize> = identifiers.iter()
+            .enumerate()
+            .map(|(index, token)| (token._symbol.unwrap(), index))
+            .collect();
+
+        // when:
+        let started = Instant::now();
+        for _ in 0..100 {
+            for token in &identifiers {
+                black_box(by_string.get(token.lexeme()));
+            }
+        }
+        let string_time = started.elapsed();
+
+        let started = Instant::now();
+        for _ in 0..100 {
+            for token in &identifiers {
+                black_box(by_symbol.get(&token._symbol.unwrap()));
+            }
+        }
+        let symbol_time = started.elapsed();
+
+        // then:
+        println!("{} lookups: String keys {:?}, Symbol keys {:?}", identifiers.len() * 100, string_time, symbol_time);
+        assert!(symbol_time < string_time);
+    }
+}
+
+#[cfg(test)]
+mod test_optimizer {
+    use crate::domain::{Expr, KeywordType, TokenType};
+    use crate::optimizer::optimize;
+    use crate::test::parse_tokens;
+
+    fn optimized(code: &str) -> Expr {
+        optimize(parse_tokens(code.to_string()).unwrap().expr, 1)
+    }
+
+    #[test]
+    fn test_fold_arithmetic() {
+
+        // given:
+        let code = "2 * 3 + 1";
+
+        // when:
+        let expr = optimized(code);
+
+        // then:
+        match expr {
+            Expr::Literal(value, token) => {
+                assert_eq!("7.0", value);
+                assert_eq!(TokenType::NUMBER, token._type);
+                assert_eq!("7.0", token.lexeme());
+            }
+            other => panic!("expected literal, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_fold_strings_comparisons_and_bang() {
+
+        // given:
+        let cases = [
+            ("\"a\" + \"b\"", TokenType::STRING, "ab"),
+            ("1 < 2", TokenType::KEYWORD(KeywordType::TRUE), "true"),
+            ("1 == \"1\"", TokenType::KEYWORD(KeywordType::FALSE), "false"),
+            ("nil == nil", TokenType::KEYWORD(KeywordType::TRUE), "true"),
+            ("!true", TokenType::KEYWORD(KeywordType::FALSE), "false"),
+            ("!nil", TokenType::KEYWORD(KeywordType::TRUE), "true"),
+            ("-(1 + 2)", TokenType::NUMBER, "-3.0"),
+        ];
+
+        for (code, expected_type, expected_value) in cases {
+
+            // when:
+            let expr = optimized(code);
+
+            // then:
+            match expr {
+                Expr::Literal(value, token) => {
+                    assert_eq!(expected_value, value, "{}", code);
+                    assert_eq!(expected_type, token._type, "{}", code);
+                }
+                other => panic!("{}: expected literal, got {:?}", code, other),
+            }
+        }
+    }
+
+    #[test]
+    fn test_fold_keeps_identifiers() {
+
+        // given:
+        let code = "x + 2 * 3";
+
+        // when:
+        let expr = optimized(code);
+
+        // then:
+        assert_eq!(" PLUS + null 6.0", expr.to_string());
+        assert!(matches!(expr, Expr::Binary(..)));
+    }
+
+    #[test]
+    fn test_redundant_grouping_removed() {
+
+        // given:
+        let code = "((1 + x)) * ((y))";
+
+        // when:
+        let expr = optimized(code);
+
+        // then:
+        match expr {
+            Expr::Binary(left, _, right) => {
+                assert!(matches!(*left, Expr::Grouping(ref inner) if matches!(**inner, Expr::Binary(..))));
+                assert!(matches!(*right, Expr::Literal(..)));
+            }
+            other => panic!("expected binary, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_runtime_errors_not_folded() {
+
+        // given:
+        let cases = ["1 / 0", "\"a\" - 1", "-\"a\"", "true + 1"];
+
+        for code in cases {
+
+            // when:
+            let expr = optimized(code);
+
+            // then:
+            assert!(!matches!(expr, Expr::Literal(..)), "{}", code);
+        }
+    }
+
+    #[test]
+    fn test_overflowing_literal_not_folded() {
+
+        // given:
+        let huge = "1".to_string() + &"0".repeat(400);
+        let cases = [format!("-{}", huge), format!("{} * 0", huge), format!("!{}", huge)];
+
+        for code in cases {
+
+            // when:
+            let expr = optimized(&code);
+
+            // then:
+            assert!(!expr.to_string().contains("inf"), "{}", code);
+            assert!(!matches!(expr, Expr::Literal(..)), "{}", code);
+        }
+    }
+
+    #[test]
+    fn test_level_zero_keeps_tree() {
+
+        // given:
+        let ast = parse_tokens("(2 * 3) + 1".to_string()).unwrap();
+        let printed = ast.expr.to_string();
+
+        // when:
+        let expr = optimize(ast.expr, 0);
+
+        // then:
+        assert!(matches!(expr, Expr::Binary(..)));
+        assert_eq!(printed, expr.to_string());
+    }
+}
+
+#[cfg(test)]
+mod test_checker {
+    use crate::checker::{check, Type};
+    use crate::domain::ParserError;
+    use crate::test::parse_tokens;
+
+    #[test]
+    fn test_infer_types() {
+
+        // given:
+        let cases = [
+            ("1 + 2 * 3", Type::Number),
+            ("\"a\" + \"b\"", Type::String),
+            ("1 < 2", Type::Bool),
+            ("!\"a\"", Type::Bool),
+            ("(nil)", Type::Nil),
+            ("x + 1", Type::Number),
+            ("x + y", Type::Any),
+        ];
+
+        for (code, expected) in cases {
+
+            // when:
+            let (_type, errors) = check(&parse_tokens(code.to_string()).unwrap().expr);
+
+            // then:
+            assert_eq!(expected, _type, "{}", code);
+            assert!(errors.is_empty(), "{}: {:?}", code, errors);
+        }
+    }
+
+    #[test]
+    fn test_mismatch_reported_at_operator() {
+
+        // given:
+        let code = "1 +\n(\"a\" - 1)";
+
+        // when:
+        let (_, errors) = check(&parse_tokens(code.to_string()).unwrap().expr);
+
+        // then:
+        assert_eq!(1, errors.len());
+        assert_eq!(2, errors[0].token._line);
+        assert_eq!("-", errors[0].token.lexeme());
+        assert_eq!("Operands must be numbers, found string and number.", errors[0].message);
+    }
+
+    #[test]
+    fn test_untyped_code_has_no_errors() {
+
+        // given:
+        let cases = ["-x", "a - b * c", "x + \"s\"", "(a < b) == c", "!nil"];
+
+        for code in cases {
+
+            // when:
+            let (_, errors) = check(&parse_tokens(code.to_string()).unwrap().expr);
+
+            // then:
+            assert!(errors.is_empty(), "{}: {:?}", code, errors);
+        }
+    }
+
+    #[test]
+    fn test_whole_chain_checked() {
+
+        // given:
+        let code = "1 + 2 + \"x\"";
+
+        // when:
+        let (_, errors) = check(&parse_tokens(code.to_string()).unwrap().expr);
+
+        // then:
+        assert_eq!(1, errors.len());
+        assert_eq!(7, errors[0].token._column_from);
+        assert_eq!("Operands must be two numbers or two strings, found number and string.", errors[0].message);
+    }
+
+    #[test]
+    fn test_trailing_code_not_skipped() {
+
+        // given:
+        let code = "1; \"a\" - 1";
+
+        // when:
+        let ast_result = parse_tokens(code.to_string());
+
+        // then:
+        match ast_result {
+            Err(ParserError::Default(message, token, _)) => {
+                assert_eq!("Expect end of expression.", message);
+                assert_eq!(";", token.lexeme());
+            }
+            other => panic!("trailing code accepted: {:?}", other)
+        }
+    }
+
+    #[test]
+    fn test_errors_do_not_cascade() {
+
+        // given:
+        let code = "(1 + \"a\") * 2 - -true";
+
+        // when:
+        let (_, errors) = check(&parse_tokens(code.to_string()).unwrap().expr);
+
+        // then:
+        let operators: Vec<&str> = errors.iter().map(|error| error.token.lexeme()).collect();
+        assert_eq!(vec!["+", "-"], operators);
+    }
+}
+
+fn parse_tokens(code: String) -> Result<AST, ParserError> {
+    let tokens = generate_tokens(code);
+    println!("{:?}", tokens);
+    parse(tokens)
+}
+
+fn generate_tokens(code: String) -> Tokens {
+    tokenize_code(code)
+}
+
+/// стек тестового потока - 2 МБ, отладочной сборке на MAX_NESTING уровней нужно больше
+#[cfg(test)]
+fn on_large_stack(run: impl FnOnce() + Send + 'static) {
+    std::thread::Builder::new()
+        .stack_size(16 * 1024 * 1024)
+        .spawn(run)
+        .expect("failed to spawn test thread")
+        .join()
+        .expect("test thread panicked")
 }
\ No newline at end of file